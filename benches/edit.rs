@@ -5,10 +5,12 @@ extern crate test;
 use kiro_editor::{Editor, InputSeq, KeySeq, Language, Result, StdinRawMode};
 use rand::rngs::StdRng;
 use rand::{Rng, SeedableRng};
-use std::fs::File;
+use std::env;
+use std::fs::{self, File};
 use std::io::{self, Write};
-use std::io::{BufRead, BufReader};
-use std::path::Path;
+use std::io::{BufRead, BufReader, BufWriter};
+use std::path::{Path, PathBuf};
+use std::process;
 use test::Bencher;
 
 fn generate_random_text(max_chars: usize) -> Vec<String> {
@@ -36,6 +38,32 @@ fn generate_random_text(max_chars: usize) -> Vec<String> {
     }
 }
 
+// About 10MB random text file generated for each benchmark. It is removed with its swap file when
+// the benchmark finishes
+struct LargeFile(PathBuf);
+
+impl LargeFile {
+    fn generate(name: &str) -> Result<Self> {
+        let file = format!("kiro-bench-{}-{}.txt", name, process::id());
+        let file = LargeFile(env::temp_dir().join(file));
+        let mut f = BufWriter::new(File::create(&file.0)?);
+        for line in generate_random_text(10_000_000) {
+            writeln!(f, "{}", line)?;
+        }
+        f.flush()?;
+        Ok(file)
+    }
+}
+
+impl Drop for LargeFile {
+    fn drop(&mut self) {
+        let name = self.0.file_name().unwrap().to_string_lossy();
+        let swap = self.0.with_file_name(format!(".{}.kiro-swp", name));
+        let _ = fs::remove_file(swap);
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
 // Ctrl-G and Ctrl-S are not included because they quits an editor and saves a file
 const VALID_CTRL_KEYS: &[u8] = b"pbnfvaedghkjwlimo?x]ur";
 const VALID_ALT_KEYS: &[u8] = b"vfbnpx<>";
@@ -77,7 +105,7 @@ impl RandomInput {
         let len_normal_keys = normal_keys.len();
         let len_special_keys = special_keys.len();
         if self.rng.gen_range(0, len_normal_keys + len_special_keys) < len_normal_keys {
-            let b = normal_keys[self.rng.gen_range(0, len_normal_keys)].clone();
+            let b = normal_keys[self.rng.gen_range(0, len_normal_keys)];
             KeySeq::Key(b)
         } else {
             special_keys[self.rng.gen_range(0, len_special_keys)].clone()
@@ -170,7 +198,7 @@ fn no_term_edit_1000_operations_to_10000_chars_plain_text(b: &mut Bencher) {
 
 #[bench]
 fn with_term_edit_1000_operations_to_editor_rs(b: &mut Bencher) -> Result<()> {
-    let f = BufReader::new(File::open(&Path::new("src/editor.rs"))?);
+    let f = BufReader::new(File::open(Path::new("src/editor.rs"))?);
     let lines = f.lines().collect::<io::Result<Vec<_>>>()?;
     let input = RandomInput::new(1000);
     let _stdin = StdinRawMode::new()?;
@@ -185,7 +213,7 @@ fn with_term_edit_1000_operations_to_editor_rs(b: &mut Bencher) -> Result<()> {
 
 #[bench]
 fn no_term_edit_1000_operations_to_editor_rs(b: &mut Bencher) -> Result<()> {
-    let f = BufReader::new(File::open(&Path::new("src/editor.rs"))?);
+    let f = BufReader::new(File::open(Path::new("src/editor.rs"))?);
     let lines = f.lines().collect::<io::Result<Vec<_>>>()?;
     let input = RandomInput::new(1000);
     b.iter(|| {
@@ -196,3 +224,30 @@ fn no_term_edit_1000_operations_to_editor_rs(b: &mut Bencher) -> Result<()> {
     });
     Ok(())
 }
+
+#[bench]
+fn no_term_edit_1000_operations_to_large_file(b: &mut Bencher) -> Result<()> {
+    let file = LargeFile::generate("edit-large-file")?;
+    let files = &[&file.0];
+    let input = RandomInput::new(1000);
+    b.iter(|| {
+        let mut editor = Editor::open(input.clone(), Discard, Some((80, 24)), files).unwrap();
+        editor.edit().unwrap();
+    });
+    Ok(())
+}
+
+#[bench]
+fn no_term_search_in_large_file(b: &mut Bencher) -> Result<()> {
+    let file = LargeFile::generate("search-large-file")?;
+    let files = &[&file.0];
+    let mut input = vec![InputSeq::ctrl(KeySeq::Key(b'g'))];
+    input.extend(b"xyz".iter().map(|b| InputSeq::new(KeySeq::Key(*b))));
+    input.push(InputSeq::new(KeySeq::Key(b'\r')));
+    b.iter(|| {
+        let input = input.iter().cloned().map(Ok);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), files).unwrap();
+        editor.edit().unwrap();
+    });
+    Ok(())
+}
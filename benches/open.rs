@@ -2,11 +2,41 @@
 
 extern crate test;
 
-use kiro_editor::{Editor, InputSeq, KeySeq, Result, StdinRawMode};
-use std::io;
-use std::path::Path;
+use kiro_editor::{Editor, InputSeq, KeySeq, Result, StdinRawMode, TextBuffer};
+use std::env;
+use std::fs;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::process;
 use test::Bencher;
 
+// About 70MB text file generated for each benchmark. It is removed when the benchmark finishes
+struct LargeFile(PathBuf);
+
+impl LargeFile {
+    fn generate(name: &str) -> Self {
+        let file = format!("kiro-bench-{}-{}.txt", name, process::id());
+        let file = LargeFile(env::temp_dir().join(file));
+        let mut f = io::BufWriter::new(fs::File::create(&file.0).unwrap());
+        for i in 0..1_000_000 {
+            writeln!(
+                f,
+                "{:>8}: Lorem ipsum dolor sit amet, consectetur adipiscing elit, sed do",
+                i
+            )
+            .unwrap();
+        }
+        f.flush().unwrap();
+        file
+    }
+}
+
+impl Drop for LargeFile {
+    fn drop(&mut self) {
+        let _ = fs::remove_file(&self.0);
+    }
+}
+
 struct NeverInput;
 
 impl Iterator for NeverInput {
@@ -45,3 +75,19 @@ fn with_term_open_highlighted_code(b: &mut Bencher) {
         editor.first_paint().unwrap();
     });
 }
+
+#[bench]
+fn no_term_open_large_file(b: &mut Bencher) {
+    let file = LargeFile::generate("open-large-file");
+    let files = &[&file.0];
+    b.iter(|| {
+        let mut editor = Editor::open(NeverInput, io::sink(), Some((80, 24)), files).unwrap();
+        editor.first_paint().unwrap();
+    });
+}
+
+#[bench]
+fn text_buffer_open_large_file(b: &mut Bencher) {
+    let file = LargeFile::generate("text-buffer-open-large-file");
+    b.iter(|| TextBuffer::open(&file.0).unwrap());
+}
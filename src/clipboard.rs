@@ -0,0 +1,116 @@
+use crate::error::{Error, Result};
+use std::io::{Read, Write};
+use std::process::{Command, Stdio};
+
+// Backend to exchange text with system clipboard
+#[derive(Default)]
+pub enum Clipboard {
+    // Send text to terminal's clipboard via OSC 52 escape sequence. This works even over SSH but
+    // reading clipboard is not supported since many terminals disable it for security reason
+    #[default]
+    Osc52,
+    // Pipe text to/from local commands such as `xclip`, `wl-copy`/`wl-paste`, `pbcopy`/`pbpaste`.
+    // Commands are run via `sh -c`
+    Command {
+        copy: String,
+        paste: Option<String>,
+    },
+}
+
+fn shell(cmd: &str) -> Command {
+    let mut c = Command::new("sh");
+    c.arg("-c").arg(cmd);
+    c
+}
+
+fn command_error<S: ToString>(cmd: &str, reason: S) -> Error {
+    Error::ClipboardCommandFailed(cmd.to_string(), reason.to_string())
+}
+
+impl Clipboard {
+    // Returns true when copied text must be written to terminal as OSC 52 sequence by caller
+    pub fn copy(&self, text: &str) -> Result<bool> {
+        let cmd = match self {
+            Clipboard::Osc52 => return Ok(true),
+            Clipboard::Command { copy, .. } => copy,
+        };
+
+        let mut child = shell(cmd)
+            .stdin(Stdio::piped())
+            .stdout(Stdio::null())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| command_error(cmd, e))?;
+        if let Some(mut stdin) = child.stdin.take() {
+            stdin
+                .write_all(text.as_bytes())
+                .map_err(|e| command_error(cmd, e))?;
+        } // Close stdin here to notify EOF to the command
+        let status = child.wait().map_err(|e| command_error(cmd, e))?;
+        if !status.success() {
+            return Err(command_error(cmd, status));
+        }
+        Ok(false)
+    }
+
+    pub fn paste(&self) -> Result<String> {
+        let cmd = match self {
+            Clipboard::Command {
+                paste: Some(paste), ..
+            } => paste,
+            _ => return Err(Error::ClipboardNotReadable),
+        };
+
+        let mut child = shell(cmd)
+            .stdin(Stdio::null())
+            .stdout(Stdio::piped())
+            .stderr(Stdio::null())
+            .spawn()
+            .map_err(|e| command_error(cmd, e))?;
+        let mut bytes = vec![];
+        if let Some(mut stdout) = child.stdout.take() {
+            stdout
+                .read_to_end(&mut bytes)
+                .map_err(|e| command_error(cmd, e))?;
+        }
+        let status = child.wait().map_err(|e| command_error(cmd, e))?;
+        if !status.success() {
+            return Err(command_error(cmd, status));
+        }
+
+        let text = String::from_utf8(bytes).map_err(|e| command_error(cmd, e))?;
+        Ok(text.replace("\r\n", "\n"))
+    }
+}
+
+fn base64(bytes: &[u8]) -> String {
+    const TABLE: &[u8; 64] = b"ABCDEFGHIJKLMNOPQRSTUVWXYZabcdefghijklmnopqrstuvwxyz0123456789+/";
+    let mut encoded = String::with_capacity(bytes.len().div_ceil(3) * 4);
+    for chunk in bytes.chunks(3) {
+        let b = [
+            chunk[0],
+            chunk.get(1).copied().unwrap_or(0),
+            chunk.get(2).copied().unwrap_or(0),
+        ];
+        let n = (b[0] as usize) << 16 | (b[1] as usize) << 8 | b[2] as usize;
+        for i in 0..4 {
+            if i <= chunk.len() {
+                encoded.push(TABLE[(n >> (18 - i * 6)) & 0x3f] as char);
+            } else {
+                encoded.push('=');
+            }
+        }
+    }
+    encoded
+}
+
+// OSC 52 sets text to clipboard: https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h3-Operating-System-Commands
+// In tmux, the sequence is wrapped with DCS passthrough so that it reaches the outer terminal.
+pub fn osc52_sequence(text: &str, in_tmux: bool) -> String {
+    let seq = format!("\x1b]52;c;{}\x07", base64(text.as_bytes()));
+    if in_tmux {
+        format!("\x1bPtmux;\x1b{}\x1b\\", seq)
+    } else {
+        seq
+    }
+}
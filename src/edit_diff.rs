@@ -1,4 +1,5 @@
-use crate::row::Row;
+use crate::rope::Rope;
+use crate::text_buffer::LineEnding;
 
 #[derive(Debug, Clone, Copy)]
 pub enum UndoRedo {
@@ -17,43 +18,44 @@ pub enum EditDiff {
     Newline,
     InsertLine(usize, String),
     DeleteLine(usize, String),
+    LineEnding(usize, usize, LineEnding, LineEnding), // Cursor, old and new line endings
 }
 
 impl EditDiff {
-    pub fn apply(&self, rows: &mut Vec<Row>, which: UndoRedo) -> (usize, usize) {
+    pub fn apply(&self, rows: &mut Rope, eol: &mut LineEnding, which: UndoRedo) -> (usize, usize) {
         // Returns cursor's next position (x, y)
         use UndoRedo::*;
         match *self {
             EditDiff::InsertChar(x, y, c) => match which {
                 Redo => {
-                    rows[y].insert_char(x, c);
+                    rows.insert_char(y, x, c);
                     (x + 1, y)
                 }
                 Undo => {
-                    rows[y].remove_char(x);
+                    rows.remove_char(y, x);
                     (x, y)
                 }
             },
             EditDiff::DeleteChar(x, y, c) => match which {
                 Redo => {
-                    rows[y].remove_char(x - 1);
+                    rows.remove_char(y, x - 1);
                     (x - 1, y)
                 }
                 Undo => {
-                    rows[y].insert_char(x - 1, c);
+                    rows.insert_char(y, x - 1, c);
                     (x, y)
                 }
             },
             EditDiff::Append(y, ref s) => match which {
                 Redo => {
                     let len = rows[y].len();
-                    rows[y].append(s);
+                    rows.append(y, s);
                     (len, y)
                 }
                 Undo => {
                     let count = s.chars().count();
                     let len = rows[y].len();
-                    rows[y].remove(len - count, len);
+                    rows.remove(y, len - count, len);
                     (rows[y].len(), y)
                 }
             },
@@ -61,40 +63,40 @@ impl EditDiff {
                 Redo => {
                     let count = s.chars().count();
                     let len = rows[y].len();
-                    rows[y].truncate(len - count);
+                    rows.truncate(y, len - count);
                     (len - count, y)
                 }
                 Undo => {
-                    rows[y].append(s);
+                    rows.append(y, s);
                     let x = rows[y].len() - s.chars().count();
                     (x, y)
                 }
             },
             EditDiff::Insert(x, y, ref s) => match which {
                 Redo => {
-                    rows[y].insert_str(x, s);
+                    rows.insert_str(y, x, s);
                     (x + s.chars().count(), y)
                 }
                 Undo => {
-                    rows[y].remove(x, s.chars().count());
+                    rows.remove(y, x, x + s.chars().count());
                     (x, y)
                 }
             },
             EditDiff::Remove(x, y, ref s) => match which {
                 Redo => {
                     let next_x = x - s.chars().count();
-                    rows[y].remove(next_x, x);
+                    rows.remove(y, next_x, x);
                     (next_x, y)
                 }
                 Undo => {
                     let count = s.chars().count();
-                    rows[y].insert_str(x - count, s);
+                    rows.insert_str(y, x - count, s);
                     (x, y)
                 }
             },
             EditDiff::Newline => match which {
                 Redo => {
-                    rows.push(Row::empty());
+                    rows.push("");
                     (0, rows.len() - 1)
                 }
                 Undo => {
@@ -105,11 +107,11 @@ impl EditDiff {
             },
             EditDiff::InsertLine(y, ref s) => match which {
                 Redo => {
-                    rows.insert(y, Row::new(s).unwrap());
+                    rows.insert(y, s);
                     (0, y)
                 }
                 Undo => {
-                    rows.remove(y);
+                    rows.remove_line(y);
                     (rows[y - 1].len(), y - 1)
                 }
             },
@@ -118,19 +120,26 @@ impl EditDiff {
                     if y == rows.len() - 1 {
                         rows.pop();
                     } else {
-                        rows.remove(y);
+                        rows.remove_line(y);
                     }
                     (rows[y - 1].len(), y - 1)
                 }
                 Undo => {
                     if y == rows.len() {
-                        rows.push(Row::new(s).unwrap());
+                        rows.push(s);
                     } else {
-                        rows.insert(y, Row::new(s).unwrap());
+                        rows.insert(y, s);
                     }
                     (0, y)
                 }
             },
+            EditDiff::LineEnding(x, y, old, new) => {
+                *eol = match which {
+                    Redo => new,
+                    Undo => old,
+                };
+                (x, y)
+            }
         }
     }
 }
@@ -1,14 +1,44 @@
+use crate::clipboard::Clipboard;
 use crate::error::Result;
-use crate::highlight::Highlighting;
+use crate::file_arg::FileArg;
+use crate::highlight::{Highlight, Highlighting, RegionHighlight};
 use crate::input::{InputSeq, KeySeq};
+use crate::kill_ring::KillRing;
 use crate::language::Language;
+use crate::layout::SplitDir;
 use crate::prompt::{self, Prompt, PromptResult};
-use crate::screen::Screen;
+use crate::save::Backup;
+use crate::screen::{LineNumbers, Screen};
 use crate::status_bar::StatusBar;
-use crate::text_buffer::{CursorDir, Lines, TextBuffer};
+use crate::text_buffer::{CursorDir, LineEnding, Lines, TextBuffer};
+use std::cmp;
 use std::io::Write;
+use std::mem;
 use std::path::Path;
 
+const READ_ONLY_MESSAGE: &str = "Buffer is read-only since binary file is shown in hex view";
+const SWAP_UPDATE_COUNT: usize = 50; // Swap file is updated after this number of edits
+
+// Command run by previous key input. Some commands behave differently when they are repeated
+#[derive(Clone, Copy, Default)]
+enum LastCommand {
+    #[default]
+    Other,
+    Kill,
+    Yank((usize, usize), (usize, usize)), // Range of yanked text
+    QuotedInsert,                         // Next key is inserted as character
+    ChangedOnDisk, // Warned external modification. Next ^S overwrites and Alt-R reloads the file
+    ConfirmReload, // Warned unsaved changes. Next Alt-R discards them
+    RecoverSwap,   // Asked recovering leftover swap file. Next 'y' or 'n' answers it
+    ConfirmClose,  // Warned unsaved changes. Next Alt-K closes the buffer
+}
+
+#[derive(Clone, Copy, PartialEq)]
+enum KillDir {
+    Forward,
+    Backward,
+}
+
 enum EditStep {
     Continue(InputSeq),
     Quit,
@@ -23,14 +53,68 @@ impl EditStep {
     }
 }
 
+// Text buffer with its highlighting and last scroll position. They are kept while the buffer is
+// hidden so that switching buffers does not highlight the whole text again
+struct Buffer {
+    text: TextBuffer,
+    hl: Highlighting,
+    rowoff: usize,
+    coloff: usize,
+    edits_since_swap: usize,
+}
+
+impl Buffer {
+    fn new(text: TextBuffer) -> Self {
+        Self {
+            hl: Highlighting::new(text.lang()),
+            text,
+            rowoff: 0,
+            coloff: 0,
+            edits_since_swap: 0,
+        }
+    }
+}
+
+// Text pasted from outside may contain CRLF newlines and stray control characters such as escape
+// sequences. Control characters can be inserted only by quoted insert
+fn sanitize_pasted(text: &str) -> String {
+    text.replace("\r\n", "\n")
+        .chars()
+        .filter(|&c| c == '\n' || c == '\t' || !c.is_control())
+        .collect()
+}
+
+// State of a split window. State of the focused window is held by `Editor` fields directly and
+// swapped with this when focus moves
+struct Window {
+    buf_idx: usize,
+    cursor: (usize, usize),
+    status_bar: StatusBar,
+}
+
+impl Window {
+    fn new(buf: &TextBuffer, buf_idx: usize, buf_pos: (usize, usize)) -> Self {
+        Self {
+            buf_idx,
+            cursor: buf.cursor(),
+            status_bar: StatusBar::from_buffer(buf, buf_pos),
+        }
+    }
+}
+
 pub struct Editor<I: Iterator<Item = Result<InputSeq>>, W: Write> {
     input: I,       // Escape sequences stream represented as Iterator
     quitting: bool, // After first Ctrl-Q
-    hl: Highlighting,
     screen: Screen<W>,
-    bufs: Vec<TextBuffer>,
+    bufs: Vec<Buffer>,
     buf_idx: usize,
     status_bar: StatusBar,
+    kill_ring: KillRing, // Shared by all buffers
+    last_command: LastCommand,
+    clipboard: Clipboard,
+    windows: Vec<Window>, // Indexed by window ID of screen
+    autosave: bool,       // Save modified buffers on idle and on switching buffers
+    backup: Backup,
 }
 
 impl<I, W> Editor<I, W>
@@ -46,14 +130,20 @@ where
     ) -> Result<Editor<I, W>> {
         let screen = Screen::new(window_size, &mut input, output)?;
         let status_bar = StatusBar::from_buffer(&buf, (1, 1));
+        let windows = vec![Window::new(&buf, 0, (1, 1))];
         Ok(Editor {
             input,
             quitting: false,
-            hl: Highlighting::default(),
             screen,
-            bufs: vec![buf],
+            bufs: vec![Buffer::new(buf)],
             buf_idx: 0,
             status_bar,
+            kill_ring: KillRing::default(),
+            last_command: LastCommand::Other,
+            clipboard: Clipboard::default(),
+            windows,
+            autosave: false,
+            backup: Backup::default(),
         })
     }
 
@@ -80,54 +170,176 @@ where
             return Self::new(input, output, window_size);
         }
         let screen = Screen::new(window_size, &mut input, output)?;
-        let bufs: Vec<_> = paths.iter().map(TextBuffer::open).collect::<Result<_>>()?;
-        let hl = Highlighting::new(bufs[0].lang(), bufs[0].rows());
-        let status_bar = StatusBar::from_buffer(&bufs[0], (1, bufs.len()));
+        let bufs: Vec<_> = paths
+            .iter()
+            .map(|p| TextBuffer::open(p).map(Buffer::new))
+            .collect::<Result<_>>()?;
+        let status_bar = StatusBar::from_buffer(&bufs[0].text, (1, bufs.len()));
+        let windows = vec![Window::new(&bufs[0].text, 0, (1, bufs.len()))];
         Ok(Editor {
             input,
             quitting: false,
-            hl,
             screen,
             bufs,
             buf_idx: 0,
             status_bar,
+            kill_ring: KillRing::default(),
+            last_command: LastCommand::Other,
+            clipboard: Clipboard::default(),
+            windows,
+            autosave: false,
+            backup: Backup::default(),
         })
     }
 
+    // Open files with initial cursor positions given from command line. Text piped to stdin is
+    // opened as the first buffer
+    pub fn open_files(
+        input: I,
+        output: W,
+        window_size: Option<(usize, usize)>,
+        files: &[FileArg],
+        piped: Option<TextBuffer>,
+    ) -> Result<Editor<I, W>> {
+        let paths: Vec<_> = files.iter().map(|f| f.path.as_str()).collect();
+        let mut editor = Self::open(input, output, window_size, &paths)?;
+        for (buf, file) in editor.bufs.iter_mut().zip(files.iter()) {
+            if let Some(line) = file.line {
+                buf.text.set_cursor_line_col(line, file.col.unwrap_or(1));
+            }
+        }
+
+        if let Some(buf) = piped {
+            if files.is_empty() {
+                // Remove empty buffer created by default
+                editor.bufs.clear();
+            }
+            editor.bufs.insert(0, Buffer::new(buf));
+        }
+
+        // Scroll so that the cursor line is at center of screen on first paint
+        let cy = editor.buf().cy();
+        editor.screen.rowoff = cy.saturating_sub(editor.screen.rows() / 2);
+        Ok(editor)
+    }
+
     pub fn buf(&self) -> &TextBuffer {
-        &self.bufs[self.buf_idx]
+        &self.bufs[self.buf_idx].text
     }
 
     fn buf_mut(&mut self) -> &mut TextBuffer {
-        &mut self.bufs[self.buf_idx]
+        &mut self.bufs[self.buf_idx].text
     }
 
     fn refresh_status_bar(&mut self) {
         self.status_bar
             .set_buf_pos((self.buf_idx + 1, self.bufs.len()));
-        self.status_bar.update_from_buf(&self.bufs[self.buf_idx]);
+        self.status_bar
+            .update_from_buf(&self.bufs[self.buf_idx].text);
     }
 
     fn render_screen(&mut self) -> Result<()> {
+        let focus = self.screen.focused_window();
+        for (id, win) in self.windows.iter_mut().enumerate() {
+            if id == focus {
+                continue;
+            }
+            let buf_pos = (win.buf_idx + 1, self.bufs.len());
+            let Buffer { text: buf, hl, .. } = &mut self.bufs[win.buf_idx];
+            // Text may be modified in other window showing the same buffer
+            let rows = buf.rows();
+            let y = cmp::min(win.cursor.1, rows.len().saturating_sub(1));
+            let x = rows.get(y).map_or(0, |r| cmp::min(win.cursor.0, r.len()));
+            win.cursor = (x, y);
+
+            win.status_bar.set_buf_pos(buf_pos);
+            win.status_bar.update_from_buf(buf);
+            win.status_bar.set_line_pos((y + 1, rows.len()));
+            self.screen
+                .render_window(id, buf, win.cursor, hl, &win.status_bar)?;
+            win.status_bar.redraw = false;
+        }
+
         self.refresh_status_bar();
+        let buf = &mut self.bufs[self.buf_idx];
         self.screen
-            .render(&self.bufs[self.buf_idx], &mut self.hl, &self.status_bar)?;
+            .render(&buf.text, &mut buf.hl, &self.status_bar)?;
         self.status_bar.redraw = false;
         Ok(())
     }
 
-    fn will_reset_scroll(&mut self) {
-        self.screen.set_dirty_start(0);
-        self.screen.rowoff = 0;
-        self.screen.coloff = 0;
-    }
-
     fn will_reset_screen(&mut self) {
         self.screen.set_dirty_start(self.screen.rowoff);
         self.screen.unset_message();
         self.status_bar.redraw = true;
     }
 
+    // Mark lines of other windows showing the current buffer as dirty after the buffer is edited
+    fn buffer_edited_at(&mut self, line: usize) {
+        let focus = self.screen.focused_window();
+        for (id, win) in self.windows.iter_mut().enumerate() {
+            if id != focus && win.buf_idx == self.buf_idx {
+                self.screen.set_window_dirty_start(id, line);
+            }
+        }
+    }
+
+    fn save_window(&mut self) {
+        let win = &mut self.windows[self.screen.focused_window()];
+        win.buf_idx = self.buf_idx;
+        win.cursor = self.bufs[self.buf_idx].text.cursor();
+        mem::swap(&mut win.status_bar, &mut self.status_bar);
+    }
+
+    fn load_window(&mut self) {
+        let win = &mut self.windows[self.screen.focused_window()];
+        self.buf_idx = win.buf_idx;
+        mem::swap(&mut win.status_bar, &mut self.status_bar);
+        let (x, y) = win.cursor;
+        self.bufs[self.buf_idx]
+            .text
+            .set_cursor_line_col(y + 1, x + 1);
+        self.status_bar.redraw = true;
+        self.screen.cursor_moved = true;
+        self.buffer_focused();
+    }
+
+    fn split_window(&mut self, dir: SplitDir) -> Result<()> {
+        if self.screen.split_window(dir)?.is_none() {
+            self.screen
+                .set_error_message("Window is too small to split");
+            return Ok(());
+        }
+        // New window shows the same buffer as the focused window
+        let buf_pos = (self.buf_idx + 1, self.bufs.len());
+        let win = Window::new(self.buf(), self.buf_idx, buf_pos);
+        self.windows.push(win);
+        self.status_bar.redraw = true;
+        Ok(())
+    }
+
+    fn close_window(&mut self) -> Result<()> {
+        let closed = self.screen.focused_window();
+        if self.screen.close_window()?.is_none() {
+            self.screen.set_info_message("Cannot close the only window");
+            return Ok(());
+        }
+        self.windows.remove(closed);
+        self.load_window();
+        Ok(())
+    }
+
+    fn other_window(&mut self) {
+        if self.screen.window_count() == 1 {
+            self.screen.set_info_message("No other window");
+            return;
+        }
+        self.save_window();
+        let next = self.screen.next_window();
+        self.screen.focus_window(next);
+        self.load_window();
+    }
+
     fn open_buffer(&mut self) -> Result<()> {
         if let PromptResult::Input(input) = self.prompt::<prompt::NoAction>(
             "Open: {} (Empty name for new text buffer, ^G or ESC to cancel)",
@@ -138,10 +350,8 @@ where
             } else {
                 TextBuffer::open(input)?
             };
-            self.hl = Highlighting::new(buf.lang(), buf.rows());
-            self.bufs.push(buf);
-            self.buf_idx = self.bufs.len() - 1;
-            self.will_reset_scroll();
+            self.bufs.push(Buffer::new(buf));
+            self.switch_to_buffer(self.bufs.len() - 1);
         }
         Ok(())
     }
@@ -154,16 +364,233 @@ where
         }
 
         debug_assert!(idx < len);
+        self.switch_to_buffer(idx);
+    }
+
+    // Show the buffer in the focused window. Scroll position where the buffer was shown last is
+    // restored
+    fn switch_to_buffer(&mut self, idx: usize) {
+        let buf = &mut self.bufs[self.buf_idx];
+        buf.rowoff = self.screen.rowoff;
+        buf.coloff = self.screen.coloff;
+
         self.buf_idx = idx;
-        let buf = self.buf();
+        let state = &self.bufs[idx];
+        self.screen.rowoff = state.rowoff;
+        self.screen.coloff = state.coloff;
+        self.screen.set_dirty_start(0);
+        self.buffer_focused();
+    }
+
+    fn buffer_focused(&mut self) {
+        self.check_file_changed();
+        self.check_leftover_swap();
+    }
+
+    // Ask user to recover the swap file left by previous session of the focused buffer
+    fn check_leftover_swap(&mut self) {
+        if let Some(pid) = self.buf().swap_owner() {
+            let msg = format!(
+                "{} is being edited by another kiro process (PID {}). Changes are not written to swap file",
+                self.buf().filename(),
+                pid,
+            );
+            self.screen.set_error_message(msg);
+        } else if let Some((added, removed)) = self.buf().leftover_swap_summary() {
+            let msg = format!(
+                "Found swap file of {} with unsaved changes (+{} -{} lines). Recover it? (y: recover, n: delete it)",
+                self.buf().filename(),
+                added,
+                removed,
+            );
+            self.screen.set_error_message(msg);
+            self.last_command = LastCommand::RecoverSwap;
+        }
+    }
+
+    fn answer_recover_swap(&mut self, recover: bool) {
+        if recover {
+            self.buf_mut().recover_swap();
+            self.screen
+                .set_info_message("Recovered unsaved changes from swap file");
+        } else {
+            self.buf_mut().discard_swap();
+            self.screen.set_info_message("Deleted swap file");
+        }
+    }
+
+    fn on_idle(&mut self) {
+        self.autosave_buffers();
+        self.write_pending_swaps();
+    }
+
+    // Save modified buffers which have files. File changed on disk is not overwritten
+    fn autosave_buffers(&mut self) {
+        if !self.autosave {
+            return;
+        }
+        let mut saved = 0;
+        let mut errors = vec![]; // Buffers which were not saved are reported together
+        for buf in self.bufs.iter_mut().map(|b| &mut b.text) {
+            if !buf.modified() || !buf.has_file() || buf.is_read_only() {
+                continue;
+            }
+            if buf.changed_on_disk() {
+                errors.push(format!("skipped {} changed on disk", buf.filename()));
+                continue;
+            }
+            if let Err(msg) = buf.save(&self.backup) {
+                errors.push(format!("failed to save {}: {}", buf.filename(), msg));
+                continue;
+            }
+            saved += 1;
+        }
+        if saved > 0 {
+            self.status_bar.redraw = true;
+        }
+        let msg = format!("Autosaved {} file(s)", saved);
+        if !errors.is_empty() {
+            self.screen
+                .set_error_message(format!("{}, {}", msg, errors.join(", ")));
+        } else if saved > 0 {
+            self.screen.set_info_message(msg);
+        }
+    }
+
+    // Write swap files of buffers edited since their swap files were written last
+    fn write_pending_swaps(&mut self) {
+        for buf in self.bufs.iter_mut() {
+            if buf.edits_since_swap == 0 || !buf.text.modified() {
+                continue;
+            }
+            buf.edits_since_swap = 0;
+            if let Err(err) = buf.text.write_swap() {
+                self.screen
+                    .set_error_message(format!("Could not write swap file: {}", err));
+                return;
+            }
+        }
+    }
+
+    // Write swap file of the current buffer on the first modification and update it after some
+    // edits. Unsaved changes can be recovered from it after crash
+    fn count_edit_for_swap(&mut self) {
+        let buf = &mut self.bufs[self.buf_idx];
+        buf.edits_since_swap += 1;
+        let pending = buf.text.swap_written() && buf.edits_since_swap < SWAP_UPDATE_COUNT;
+        if pending || !buf.text.modified() {
+            return;
+        }
+        buf.edits_since_swap = 0;
+        if let Err(err) = buf.text.write_swap() {
+            self.screen
+                .set_error_message(format!("Could not write swap file: {}", err));
+        }
+    }
+
+    // Check the file of the focused buffer was not modified by other program. Unmodified buffer
+    // is reloaded silently. Otherwise user chooses reloading or overwriting the file
+    fn check_file_changed(&mut self) {
+        if !self.buf().changed_on_disk() {
+            return;
+        }
+        if self.buf().modified() {
+            self.screen.set_error_message(
+                "File was changed on disk! Press Alt-R to reload it discarding changes or ^S to overwrite it",
+            );
+            self.last_command = LastCommand::ChangedOnDisk;
+        } else if self.reload_buffer() {
+            let msg = format!("Reloaded {} changed on disk", self.buf().filename());
+            self.screen.set_info_message(msg);
+        }
+    }
+
+    // Returns false when the file could not be read
+    fn reload_buffer(&mut self) -> bool {
+        if let Err(err) = self.buf_mut().reload() {
+            self.screen
+                .set_error_message(format!("Could not reload: {}", err));
+            return false;
+        }
+        let buf = &self.bufs[self.buf_idx].text;
+        self.bufs[self.buf_idx].hl = Highlighting::new(buf.lang());
+        self.screen.set_dirty_start(self.screen.rowoff);
+        self.buffer_edited_at(0);
+        self.screen.cursor_moved = true;
+        true
+    }
+
+    fn reload(&mut self, last: LastCommand) {
+        if !self.buf().has_file() {
+            self.screen.set_info_message("Buffer has no file to reload");
+            return;
+        }
+        let confirmed = matches!(
+            last,
+            LastCommand::ChangedOnDisk | LastCommand::ConfirmReload
+        );
+        if self.buf().modified() && !confirmed {
+            self.screen.set_error_message(
+                "Buffer has unsaved changes! Press Alt-R again to discard them and reload",
+            );
+            self.last_command = LastCommand::ConfirmReload;
+            return;
+        }
+        if self.reload_buffer() {
+            let msg = format!("Reloaded {}", self.buf().filename());
+            self.screen.set_info_message(msg);
+        }
+    }
+
+    // Close the current buffer and show the next buffer in the focused window. Empty buffer is
+    // opened when the last buffer is closed
+    fn close_buffer(&mut self, last: LastCommand) {
+        if self.buf().modified() && !matches!(last, LastCommand::ConfirmClose) {
+            self.screen.set_error_message(
+                "Buffer has unsaved changes! Press Alt-K again to close it discarding them",
+            );
+            self.last_command = LastCommand::ConfirmClose;
+            return;
+        }
 
-        // XXX: Should we put Highlighting instance in TextBuffer rather than Editor?
-        // Then we don't need to recreate Highlighting instance for each buffer switch.
-        self.hl = Highlighting::new(buf.lang(), buf.rows());
-        self.will_reset_scroll();
+        let closed = self.buf_idx;
+        let mut buf = self.bufs.remove(closed).text;
+        // Unsaved changes were discarded. Leftover swap file is kept while it is not answered
+        buf.remove_swap();
+        if self.bufs.is_empty() {
+            self.bufs.push(Buffer::new(TextBuffer::empty()));
+        }
+        let idx = cmp::min(closed, self.bufs.len() - 1);
+
+        // Other windows showing the closed buffer show the same buffer as the focused window
+        let focus = self.screen.focused_window();
+        for (id, win) in self.windows.iter_mut().enumerate() {
+            if id == focus {
+                continue;
+            }
+            if win.buf_idx == closed {
+                win.buf_idx = idx;
+                win.cursor = self.bufs[idx].text.cursor();
+                self.screen.set_window_dirty_start(id, 0);
+            } else if win.buf_idx > closed {
+                win.buf_idx -= 1;
+            }
+        }
+
+        self.buf_idx = idx;
+        let state = &self.bufs[idx];
+        self.screen.rowoff = state.rowoff;
+        self.screen.coloff = state.coloff;
+        self.screen.set_dirty_start(0);
+        self.screen.cursor_moved = true;
+        self.status_bar.redraw = true;
+        self.screen
+            .set_info_message(format!("Closed {}", buf.filename()));
+        self.buffer_focused();
     }
 
     fn next_buffer(&mut self) {
+        self.autosave_buffers();
         self.switch_buffer(if self.buf_idx == self.bufs.len() - 1 {
             0
         } else {
@@ -172,6 +599,7 @@ where
     }
 
     fn previous_buffer(&mut self) {
+        self.autosave_buffers();
         self.switch_buffer(if self.buf_idx == 0 {
             self.bufs.len() - 1
         } else {
@@ -184,24 +612,34 @@ where
         prompt: &str,
         empty_is_cancel: bool,
     ) -> Result<PromptResult> {
+        let buf = &mut self.bufs[self.buf_idx];
         Prompt::new(
             &mut self.screen,
-            &mut self.bufs[self.buf_idx],
-            &mut self.hl,
+            &mut buf.text,
+            &mut buf.hl,
             &mut self.status_bar,
             empty_is_cancel,
         )
         .run::<A, _, _>(prompt, &mut self.input)
     }
 
-    fn save(&mut self) -> Result<()> {
+    fn save(&mut self, last: LastCommand) -> Result<()> {
+        if self.buf().changed_on_disk() && !matches!(last, LastCommand::ChangedOnDisk) {
+            self.screen.set_error_message(
+                "File was changed on disk! Press ^S again to overwrite it or Alt-R to reload it",
+            );
+            self.last_command = LastCommand::ChangedOnDisk;
+            return Ok(());
+        }
+
         let mut create = false;
         if !self.buf().has_file() {
             let template = "Save as: {} (^G or ESC to cancel)";
             if let PromptResult::Input(input) = self.prompt::<prompt::NoAction>(template, true)? {
                 let prev_lang = self.buf().lang();
                 self.buf_mut().set_file(input);
-                self.hl.lang_changed(self.buf().lang());
+                let lang = self.buf().lang();
+                self.bufs[self.buf_idx].hl.lang_changed(lang);
                 if prev_lang != self.buf().lang() {
                     // Render entire screen since highglight updated
                     self.screen.set_dirty_start(self.screen.rowoff);
@@ -210,7 +648,7 @@ where
             }
         }
 
-        match self.buf_mut().save() {
+        match self.bufs[self.buf_idx].text.save(&self.backup) {
             Ok(msg) => self.screen.set_info_message(msg),
             Err(msg) => {
                 self.screen.set_error_message(msg);
@@ -224,11 +662,78 @@ where
     }
 
     fn find(&mut self) -> Result<()> {
-        let template = "Search: {} (^F or ^N or RIGHT to forward, ^B or ^P or LEFT to back, ^G or ESC to cancel)";
+        if self.buf().is_read_only() {
+            let template = "Search bytes: {} (hex like 7f 45 4c, ^F or ^N or RIGHT to forward, ^B or ^P or LEFT to back, ^G or ESC to cancel)";
+            self.prompt::<prompt::HexSearch>(template, true)?;
+            return Ok(());
+        }
+        let template = "Search: {} (^F or ^N or RIGHT to forward, ^B or ^P or LEFT to back, M-R to toggle regex, M-C to toggle case, ^G or ESC to cancel)";
         self.prompt::<prompt::TextSearch>(template, true)?;
         Ok(())
     }
 
+    fn query_replace(&mut self) -> Result<()> {
+        if self.buf().is_read_only() {
+            self.screen.set_error_message(READ_ONLY_MESSAGE);
+            return Ok(());
+        }
+        let template =
+            "Query replace: {} (M-R to toggle regex, M-C to toggle case, ^G or ESC to cancel)";
+        let buf = &mut self.bufs[self.buf_idx];
+        let (query, matcher) = match Prompt::new(
+            &mut self.screen,
+            &mut buf.text,
+            &mut buf.hl,
+            &mut self.status_bar,
+            true,
+        )
+        .read_query(template, &mut self.input)?
+        {
+            Some(query) => query,
+            None => return Ok(()),
+        };
+
+        // Note: Query is put in suffix since the template is split at first {}
+        let template = format!(
+            "{}Replace with: {{}} (replacing '{}', ^G or ESC to cancel)",
+            matcher.label(),
+            query
+        );
+        let replacement = match self.prompt::<prompt::NoAction>(&template, false)? {
+            PromptResult::Input(input) => input,
+            PromptResult::Canceled => return Ok(()),
+        };
+
+        let buf = &mut self.bufs[self.buf_idx];
+        let count = Prompt::new(
+            &mut self.screen,
+            &mut buf.text,
+            &mut buf.hl,
+            &mut self.status_bar,
+            false,
+        )
+        .query_replace(&query, &matcher, &replacement, &mut self.input)?;
+        if count > 0 {
+            self.buffer_edited_at(0);
+        }
+
+        let s = if count == 1 { "" } else { "s" };
+        self.screen
+            .set_info_message(format!("Replaced {} occurrence{}", count, s));
+        Ok(())
+    }
+
+    fn go_to_line(&mut self) -> Result<()> {
+        if self.buf().is_read_only() {
+            let template = "Go to offset: {} (offset in hex, ^G or ESC to cancel)";
+            self.prompt::<prompt::GoToLine>(template, true)?;
+            return Ok(());
+        }
+        let template = "Go to line: {} (LINE, LINE:COL, +N or -N, ^G or ESC to cancel)";
+        self.prompt::<prompt::GoToLine>(template, true)?;
+        Ok(())
+    }
+
     fn show_help(&mut self) -> Result<()> {
         self.screen.render_help()?;
 
@@ -241,19 +746,226 @@ where
                 self.screen.render_help()?;
                 self.status_bar.redraw = true;
             }
-            if seq?.key != KeySeq::Unidentified {
+            // Pseudo keys are not input by user
+            if !matches!(
+                seq?.key,
+                KeySeq::Unidentified | KeySeq::FocusIn | KeySeq::Idle
+            ) {
                 break;
             }
         }
 
         // Redraw screen after closing help
-        self.screen.set_dirty_start(self.screen.rowoff);
+        self.screen.set_all_dirty();
+        self.status_bar.redraw = true;
+        Ok(())
+    }
+
+    fn toggle_mark(&mut self) {
+        if self.buf_mut().toggle_mark() {
+            self.screen.set_info_message("Mark set");
+        } else {
+            self.screen.set_info_message("Mark deactivated");
+        }
+    }
+
+    fn kill(&mut self, killed: Option<String>, dir: KillDir, last: LastCommand) {
+        if let Some(killed) = killed {
+            match (last, dir) {
+                // Consecutive kills are concatenated into one entry
+                (LastCommand::Kill, KillDir::Forward) => self.kill_ring.append(&killed),
+                (LastCommand::Kill, KillDir::Backward) => self.kill_ring.prepend(&killed),
+                _ => self.kill_ring.push(killed),
+            }
+        }
+        self.last_command = LastCommand::Kill;
+    }
+
+    fn kill_word_or_region(&mut self, last: LastCommand) {
+        if let Some(killed) = self.buf_mut().delete_region() {
+            self.kill(Some(killed), KillDir::Forward, last);
+        } else {
+            let killed = self.buf_mut().delete_word();
+            self.kill(killed, KillDir::Backward, last);
+        }
+    }
+
+    fn copy_region(&mut self) {
+        if let Some(text) = self.buf().region_text() {
+            self.kill_ring.push(text);
+            self.buf_mut().clear_mark();
+            self.screen.set_info_message("Copied region");
+        } else {
+            self.screen
+                .set_error_message("No region is selected. Set mark by ^SPACE");
+        }
+    }
+
+    fn yank(&mut self) {
+        let text = if let Some(text) = self.kill_ring.head() {
+            text.to_string()
+        } else {
+            self.screen.set_error_message("Kill ring is empty");
+            return;
+        };
+        let start = self.buf().cursor();
+        self.buf_mut().insert_str(&text);
+        self.last_command = LastCommand::Yank(start, self.buf().cursor());
+    }
+
+    fn copy_to_clipboard(&mut self) -> Result<()> {
+        let text = if let Some(text) = self.kill_ring.head() {
+            text.to_string()
+        } else {
+            self.screen.set_error_message("Kill ring is empty");
+            return Ok(());
+        };
+        match self.clipboard.copy(&text) {
+            Ok(true) => self.screen.write_clipboard(&text)?,
+            Ok(false) => {}
+            Err(err) => {
+                self.screen.set_error_message(format!("{}", err));
+                return Ok(());
+            }
+        }
+        self.screen.set_info_message("Copied to clipboard");
         Ok(())
     }
 
+    fn paste_from_clipboard(&mut self) {
+        match self.clipboard.paste() {
+            Ok(text) if text.is_empty() => self.screen.set_info_message("Clipboard is empty"),
+            Ok(text) => {
+                let text = sanitize_pasted(&text);
+                // Pasted text is put to kill ring so that it can be yanked again
+                self.kill_ring.push(text);
+                self.yank();
+            }
+            Err(err) => self.screen.set_error_message(format!("{}", err)),
+        }
+    }
+
+    fn yank_pop(&mut self, last: LastCommand) {
+        let (start, end) = if let LastCommand::Yank(start, end) = last {
+            (start, end)
+        } else {
+            self.screen
+                .set_error_message("Previous command was not a yank. Yank text by ^Y first");
+            return;
+        };
+        let text = self.kill_ring.rotate().unwrap().to_string(); // Ring is not empty since text was yanked
+        self.buf_mut().replace_range(start, end, &text);
+        self.last_command = LastCommand::Yank(start, self.buf().cursor());
+    }
+
+    fn insert_tab_or_indent_region(&mut self) {
+        if self.buf().mark().is_some() {
+            self.buf_mut().indent_region();
+        } else {
+            self.buf_mut().insert_tab();
+        }
+    }
+
+    fn cycle_line_numbers(&mut self) {
+        let next = self.screen.line_numbers().next();
+        self.screen.set_line_numbers(next);
+        let mode = match next {
+            LineNumbers::Off => "off",
+            LineNumbers::Absolute => "absolute",
+            LineNumbers::Relative => "relative",
+        };
+        self.screen
+            .set_info_message(format!("Line numbers: {}", mode));
+    }
+
+    fn toggle_soft_wrap(&mut self) {
+        let enabled = !self.screen.soft_wrap();
+        self.screen.set_soft_wrap(enabled);
+        let state = if enabled { "on" } else { "off" };
+        self.screen
+            .set_info_message(format!("Soft wrap: {}", state));
+    }
+
+    fn start_quoted_insert(&mut self) {
+        self.screen
+            .set_info_message("Quoted insert: Type a key to insert as character");
+        self.last_command = LastCommand::QuotedInsert;
+    }
+
+    // Insert the key as character literally. Control characters can be inserted in this way
+    fn insert_quoted(&mut self, seq: &InputSeq) {
+        use KeySeq::*;
+        let c = match seq {
+            InputSeq {
+                key: Key(b),
+                ctrl: true,
+                alt: false,
+            } => (b & 0b0001_1111) as char, // Ctrl mod masks key with 0b11111
+            InputSeq {
+                key: Key(b),
+                ctrl: false,
+                alt: false,
+            } => *b as char,
+            InputSeq {
+                key: Utf8Key(c),
+                ctrl: false,
+                alt: false,
+            } => *c,
+            _ => {
+                self.screen
+                    .set_error_message(format!("Key '{}' cannot be inserted", seq));
+                return;
+            }
+        };
+        if c == '\n' {
+            self.buf_mut().insert_line(); // Line never contains newline
+        } else {
+            self.buf_mut().insert_char(c);
+        }
+    }
+
+    fn convert_line_ending(&mut self) {
+        let to = match self.buf().line_ending() {
+            LineEnding::Lf => LineEnding::CrLf,
+            LineEnding::CrLf => LineEnding::Lf,
+        };
+        self.buf_mut().set_line_ending(to);
+        self.screen
+            .set_info_message(format!("Line endings: {}", to.name()));
+    }
+
+    // In soft wrap mode, cursor moves up/down by visual line on screen
+    fn move_cursor_vertically(&mut self, dir: CursorDir) {
+        let buf = &self.bufs[self.buf_idx].text;
+        match self.screen.next_visual_line(buf.rows(), buf.cursor(), dir) {
+            Some((x, y)) => self.buf_mut().set_cursor(x, y),
+            None => self.buf_mut().move_cursor_one(dir),
+        }
+    }
+
+    fn update_selection(&mut self) {
+        let buf = &self.bufs[self.buf_idx].text;
+        let selection = buf.region().map(|((sx, sy), (ex, ey))| {
+            let rows = buf.rows();
+            // Highlights are calculated per character in render text
+            RegionHighlight {
+                hl: Highlight::Selection,
+                start: (rows[sy].render_idx_from_cx(sx), sy),
+                end: (rows[ey].render_idx_from_cx(ex), ey),
+            }
+        });
+        if let Some(line) = self.bufs[self.buf_idx].hl.set_selection(selection) {
+            self.screen.set_dirty_start(line);
+        }
+    }
+
     fn handle_quit(&mut self, s: InputSeq) -> EditStep {
-        let modified = self.bufs.iter().any(|b| b.modified());
+        let modified = self.bufs.iter().any(|b| b.text.modified());
         if !modified || self.quitting {
+            // Swap files are no longer necessary after quitting normally
+            for buf in self.bufs.iter_mut() {
+                buf.text.remove_swap();
+            }
             EditStep::Quit
         } else {
             self.quitting = true;
@@ -275,11 +987,31 @@ where
         let rowoff = self.screen.rowoff;
         let rows = self.screen.rows();
         let prev_cursor = self.buf().cursor();
+        let last = mem::take(&mut self.last_command);
 
         match &s {
             InputSeq {
                 key: Unidentified, ..
-            } => return Ok(EditStep::Continue(s)),
+            } => {
+                self.last_command = last;
+                return Ok(EditStep::Continue(s));
+            }
+            InputSeq { key: FocusIn, .. } => {
+                self.last_command = last;
+                self.check_file_changed();
+                return Ok(EditStep::Continue(s));
+            }
+            InputSeq { key: Idle, .. } => {
+                self.last_command = last;
+                self.on_idle();
+                return Ok(EditStep::Continue(s));
+            }
+            _ if matches!(last, LastCommand::QuotedInsert) => self.insert_quoted(&s),
+            InputSeq {
+                key: Key(b @ (b'y' | b'n')),
+                ctrl: false,
+                alt: false,
+            } if matches!(last, LastCommand::RecoverSwap) => self.answer_recover_swap(*b == b'y'),
             InputSeq { key, alt: true, .. } => match key {
                 Key(b'v') => self.buf_mut().move_cursor_page(CursorDir::Up, rowoff, rows),
                 Key(b'f') => self.buf_mut().move_cursor_by_word(CursorDir::Right),
@@ -287,8 +1019,24 @@ where
                 Key(b'n') => self.buf_mut().move_cursor_paragraph(CursorDir::Down),
                 Key(b'p') => self.buf_mut().move_cursor_paragraph(CursorDir::Up),
                 Key(b'x') => self.previous_buffer(),
+                Key(b'w') => self.copy_region(),
+                Key(b'y') => self.yank_pop(last),
+                Key(b'c') => self.copy_to_clipboard()?,
+                Key(b'i') => self.paste_from_clipboard(),
                 Key(b'<') => self.buf_mut().move_cursor_to_buffer_edge(CursorDir::Up),
                 Key(b'>') => self.buf_mut().move_cursor_to_buffer_edge(CursorDir::Down),
+                Key(b'%') => self.query_replace()?,
+                Key(b'g') => self.go_to_line()?,
+                Key(b'l') => self.cycle_line_numbers(),
+                Key(b's') => self.toggle_soft_wrap(),
+                Key(b'e') => self.convert_line_ending(),
+                Key(b'2') => self.split_window(SplitDir::Horizontal)?,
+                Key(b'3') => self.split_window(SplitDir::Vertical)?,
+                Key(b'0') => self.close_window()?,
+                Key(b'o') => self.other_window(),
+                Key(b'q') => self.start_quoted_insert(),
+                Key(b'r') => self.reload(last),
+                Key(b'k') => self.close_buffer(last),
                 LeftKey => self.buf_mut().move_cursor_to_buffer_edge(CursorDir::Left),
                 RightKey => self.buf_mut().move_cursor_to_buffer_edge(CursorDir::Right),
                 _ => self.handle_not_mapped(&s),
@@ -296,9 +1044,9 @@ where
             InputSeq {
                 key, ctrl: true, ..
             } => match key {
-                Key(b'p') => self.buf_mut().move_cursor_one(CursorDir::Up),
+                Key(b'p') => self.move_cursor_vertically(CursorDir::Up),
                 Key(b'b') => self.buf_mut().move_cursor_one(CursorDir::Left),
-                Key(b'n') => self.buf_mut().move_cursor_one(CursorDir::Down),
+                Key(b'n') => self.move_cursor_vertically(CursorDir::Down),
                 Key(b'f') => self.buf_mut().move_cursor_one(CursorDir::Right),
                 Key(b'v') => self
                     .buf_mut()
@@ -308,16 +1056,24 @@ where
                 Key(b'd') => self.buf_mut().delete_right_char(),
                 Key(b'g') => self.find()?,
                 Key(b'h') => self.buf_mut().delete_char(),
-                Key(b'k') => self.buf_mut().delete_until_end_of_line(),
-                Key(b'j') => self.buf_mut().delete_until_head_of_line(),
-                Key(b'w') => self.buf_mut().delete_word(),
+                Key(b'k') => {
+                    let killed = self.buf_mut().delete_until_end_of_line();
+                    self.kill(killed, KillDir::Forward, last);
+                }
+                Key(b'j') => {
+                    let killed = self.buf_mut().delete_until_head_of_line();
+                    self.kill(killed, KillDir::Backward, last);
+                }
+                Key(b'w') => self.kill_word_or_region(last),
+                Key(b'y') => self.yank(),
+                Key(b' ') => self.toggle_mark(),
                 Key(b'l') => {
-                    self.screen.set_dirty_start(self.screen.rowoff); // Clear
+                    self.screen.set_all_dirty(); // Clear
                     self.screen.unset_message();
                     self.status_bar.redraw = true;
                 }
-                Key(b's') => self.save()?,
-                Key(b'i') => self.buf_mut().insert_tab(),
+                Key(b's') => self.save(last)?,
+                Key(b'i') => self.insert_tab_or_indent_region(),
                 Key(b'm') => self.buf_mut().insert_line(),
                 Key(b'o') => self.open_buffer()?,
                 Key(b'?') => self.show_help()?,
@@ -349,9 +1105,10 @@ where
                 Key(b'\r') => self.buf_mut().insert_line(),
                 Key(b) if !b.is_ascii_control() => self.buf_mut().insert_char(*b as char),
                 Utf8Key(c) => self.buf_mut().insert_char(*c),
-                UpKey => self.buf_mut().move_cursor_one(CursorDir::Up),
+                Paste(text) => self.buf_mut().insert_str(&sanitize_pasted(text)),
+                UpKey => self.move_cursor_vertically(CursorDir::Up),
                 LeftKey => self.buf_mut().move_cursor_one(CursorDir::Left),
-                DownKey => self.buf_mut().move_cursor_one(CursorDir::Down),
+                DownKey => self.move_cursor_vertically(CursorDir::Down),
                 RightKey => self.buf_mut().move_cursor_one(CursorDir::Right),
                 PageUpKey => self.buf_mut().move_cursor_page(CursorDir::Up, rowoff, rows),
                 PageDownKey => self
@@ -365,10 +1122,16 @@ where
             },
         }
 
+        if self.buf_mut().take_edit_rejected() {
+            self.screen.set_error_message(READ_ONLY_MESSAGE);
+        }
         if let Some(line) = self.buf_mut().finish_edit() {
-            self.hl.needs_update = true;
+            self.bufs[self.buf_idx].hl.needs_update = true;
             self.screen.set_dirty_start(line);
+            self.buffer_edited_at(line);
+            self.count_edit_for_swap();
         }
+        self.update_selection();
         if self.buf().cursor() != prev_cursor {
             self.screen.cursor_moved = true;
         }
@@ -397,6 +1160,7 @@ where
     }
 
     pub fn first_paint(&mut self) -> Result<Edit<'_, I, W>> {
+        self.check_leftover_swap();
         if self.buf().is_scratch() {
             self.screen.render_welcome(&self.status_bar)?;
             self.status_bar.redraw = false;
@@ -423,13 +1187,33 @@ where
         self.buf().lang()
     }
 
-    pub fn set_lang(&mut self, lang: Language) {
-        let buf = self.buf_mut();
-        if buf.lang() == lang {
-            return;
-        }
-        buf.set_lang(lang);
-        self.hl = Highlighting::new(lang, buf.rows());
+    pub fn set_clipboard(&mut self, clipboard: Clipboard) {
+        self.clipboard = clipboard;
+    }
+
+    pub fn set_line_numbers(&mut self, line_numbers: LineNumbers) {
+        self.screen.set_line_numbers(line_numbers);
+    }
+
+    pub fn set_soft_wrap(&mut self, enabled: bool) {
+        self.screen.set_soft_wrap(enabled);
+    }
+
+    pub fn set_autosave(&mut self, enabled: bool) {
+        self.autosave = enabled;
+    }
+
+    pub fn set_backup(&mut self, backup: Backup) {
+        self.backup = backup;
+    }
+
+    pub fn set_lang(&mut self, lang: Language) {
+        let buf = self.buf_mut();
+        if buf.lang() == lang {
+            return;
+        }
+        buf.set_lang(lang);
+        self.bufs[self.buf_idx].hl = Highlighting::new(lang);
     }
 }
 
@@ -465,185 +1249,1629 @@ where
             Err(err) => Some(Err(err)),
         }
     }
-}
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::clipboard::Clipboard;
+    use crate::editor::{Editor, SWAP_UPDATE_COUNT};
+    use crate::error::Result;
+    use crate::file_arg::FileArg;
+    use crate::highlight::Highlight;
+    use crate::input::{InputSeq, KeySeq};
+    use crate::language::Language;
+    use crate::save::{Backup, BackupMode};
+    use crate::screen::LineNumbers;
+    use crate::text_buffer::{LineEnding, TextBuffer};
+    use std::env;
+    use std::fs::{self, File};
+    use std::io::{self, BufRead, BufReader, Write};
+    use std::path::{Path, PathBuf};
+    use std::process;
+    use std::rc::Rc;
+
+    use KeySeq::*;
+
+    struct DummyInputs(Vec<InputSeq>);
+
+    impl Iterator for DummyInputs {
+        type Item = Result<InputSeq>;
+
+        fn next(&mut self) -> Option<Self::Item> {
+            if self.0.is_empty() {
+                None
+            } else {
+                Some(Ok(self.0.remove(0)))
+            }
+        }
+    }
+
+    struct Discard;
+
+    impl Write for Discard {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    fn key(c: char) -> InputSeq {
+        InputSeq::new(Key(c as u8))
+    }
+
+    fn ctrl(c: char) -> InputSeq {
+        InputSeq::ctrl(Key(c as u8))
+    }
+
+    fn alt(c: char) -> InputSeq {
+        InputSeq::alt(Key(c as u8))
+    }
+
+    fn sp(k: KeySeq) -> InputSeq {
+        if let Key(_) = k {
+            panic!("{:?}", k);
+        }
+        InputSeq::new(k)
+    }
+
+    fn utf8(c: char) -> InputSeq {
+        InputSeq::new(Utf8Key(c))
+    }
+
+    #[test]
+    fn empty_buffer() {
+        let input = DummyInputs(vec![InputSeq::ctrl(Key(b'q'))]);
+        let mut editor = Editor::new(input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        assert!(editor.screen().rows() > 0);
+        assert!(editor.screen().cols() > 0);
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec![""]);
+
+        let msg = editor.screen().message_text();
+        assert_eq!(msg, "Ctrl-? for help");
+    }
+
+    #[test]
+    fn write_to_empty_buffer() {
+        let input = DummyInputs(vec![key('a'), key('b'), key('c'), ctrl('q'), ctrl('q')]);
+        let mut editor = Editor::new(input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        let lines = editor.lines().collect::<Vec<_>>();
+        assert_eq!(lines, vec!["abc"]);
+
+        let msg = editor.screen().message_text();
+        assert!(
+            msg.contains("At least one file has unsaved changes!"),
+            "{}",
+            msg
+        );
+    }
+
+    #[test]
+    fn edit_step_by_step() {
+        let keys = vec![key('a'), key('b'), key('c'), ctrl('q'), ctrl('q')];
+        let input = DummyInputs(keys.clone());
+        let mut editor = Editor::new(input, Discard, Some((80, 24))).unwrap();
+        let mut editing = editor.first_paint().unwrap();
+
+        let mut keys = keys.iter();
+        let mut xs = [1, 2, 3, 3].iter();
+        while let Some(res) = editing.next() {
+            let key = res.unwrap();
+            let (x, y) = editing.editor().buf().cursor();
+            assert_eq!(y, 0);
+            assert_eq!(*xs.next().unwrap(), x);
+            assert_eq!(keys.next().unwrap(), &key);
+        }
+
+        let mut lines = editor.lines();
+        assert_eq!(lines.len(), 1);
+        let line = lines.next().unwrap();
+        assert_eq!(line, "abc");
+    }
+
+    #[test]
+    fn move_cursor_down() {
+        let input = DummyInputs(vec![
+            key('a'),
+            sp(DownKey),
+            key('b'),
+            sp(DownKey),
+            key('c'),
+            ctrl('q'),
+            ctrl('q'),
+        ]);
+        let mut editor = Editor::new(input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        assert!(editor.screen().rows() > 0);
+        assert!(editor.screen().cols() > 0);
+
+        let lines = editor.lines().collect::<Vec<_>>();
+        assert_eq!(lines, vec!["a", "b", "c"]);
+    }
+
+    #[test]
+    fn open_file() {
+        let input = DummyInputs(vec![ctrl('q')]);
+
+        let this_file = file!();
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[this_file]).unwrap();
+        editor.edit().unwrap();
+
+        let f = BufReader::new(File::open(this_file).unwrap());
+        for (i, (expected, actual)) in f.lines().zip(editor.lines()).enumerate() {
+            assert_eq!(expected.unwrap(), actual, "Line: {}", i + 1);
+        }
+
+        assert_eq!(editor.lang(), Language::Rust);
+    }
+
+    #[test]
+    fn message_bar_squashed() {
+        let input = DummyInputs(vec![ctrl('l'), sp(Unidentified), ctrl('q')]);
+        let mut buf = Vec::new();
+        let mut editor = Editor::new(input, &mut buf, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        let msg = editor.screen().message_text();
+        assert_eq!(msg, "");
+    }
+
+    #[test]
+    fn undo_modified() {
+        let input = DummyInputs(vec![
+            key('a'),
+            key('b'),
+            key('c'),
+            ctrl('m'),
+            ctrl('u'),
+            ctrl('u'),
+            ctrl('q'),
+            ctrl('q'),
+        ]);
+        let mut editor = Editor::new(input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        let lines = editor.lines().collect::<Vec<_>>();
+        assert_eq!(lines, vec![""]);
+
+        assert!(!editor.bufs[0].text.modified());
+    }
+
+    #[test]
+    fn select_region() {
+        let input = DummyInputs(vec![
+            sp(RightKey),
+            ctrl(' '),
+            sp(DownKey),
+            sp(RightKey),
+            ctrl('q'),
+        ]);
+        let mut editor =
+            Editor::with_lines(["abc", "d\tef"].iter(), input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(editor.buf().region(), Some(((1, 0), (2, 1))));
+        assert_eq!(editor.buf().region_text().unwrap(), "bc\nd\t");
+
+        use crate::highlight::Highlight::*;
+        assert_eq!(
+            editor.bufs[0].hl.lines[0],
+            vec![Normal, Selection, Selection]
+        );
+        // Tab is rendered as 7 spaces at the second column
+        let mut expected = vec![Selection; 8];
+        expected.extend(&[Normal, Normal]);
+        assert_eq!(editor.bufs[0].hl.lines[1], expected);
+    }
+
+    #[test]
+    fn set_mark_in_empty_file() {
+        let path = temp_file_path("mark-empty");
+        fs::write(&path, "").unwrap();
+        let input = DummyInputs(vec![ctrl(' '), ctrl('w'), key('a')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.edit().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["a"]);
+    }
+
+    #[test]
+    fn yank_pop_without_yank() {
+        let input = DummyInputs(vec![ctrl('k'), sp(DownKey), alt('y')]);
+        let mut editor =
+            Editor::with_lines(["abc", "def"].iter(), input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["", "def"]);
+        let msg = editor.screen().message_text();
+        assert!(msg.contains("Previous command was not a yank"), "{}", msg);
+    }
+
+    #[test]
+    fn copy_region_and_yank() {
+        let input = DummyInputs(vec![
+            ctrl(' '),
+            sp(DownKey),
+            alt('w'),
+            ctrl('y'),
+            ctrl('q'),
+            ctrl('q'),
+        ]);
+        let mut editor =
+            Editor::with_lines(["abc", "def"].iter(), input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(
+            editor.lines().collect::<Vec<_>>(),
+            vec!["abc", "abc", "def"]
+        );
+        assert_eq!(editor.buf().region(), None);
+    }
+
+    fn temp_file_path(name: &str) -> PathBuf {
+        env::temp_dir().join(format!("kiro-test-{}-{}", name, process::id()))
+    }
+
+    #[test]
+    fn copy_to_clipboard_with_command() {
+        let path = temp_file_path("clipboard-copy");
+        let input = DummyInputs(vec![ctrl('k'), alt('c'), ctrl('q'), ctrl('q')]);
+        let mut editor =
+            Editor::with_lines(["abc", "def"].iter(), input, Discard, Some((80, 24))).unwrap();
+        editor.set_clipboard(Clipboard::Command {
+            copy: format!("cat > '{}'", path.display()),
+            paste: None,
+        });
+        editor.edit().unwrap();
+
+        let copied = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(copied, "abc");
+    }
+
+    #[test]
+    fn paste_from_clipboard_with_command() {
+        let path = temp_file_path("clipboard-paste");
+        fs::write(&path, "foo\r\nbar\x1b").unwrap();
+        let input = DummyInputs(vec![sp(RightKey), alt('i'), ctrl('q'), ctrl('q')]);
+        let mut editor =
+            Editor::with_lines(["abc"].iter(), input, Discard, Some((80, 24))).unwrap();
+        editor.set_clipboard(Clipboard::Command {
+            copy: "true".to_string(),
+            paste: Some(format!("cat '{}'", path.display())),
+        });
+        editor.edit().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["afoo", "barbc"]);
+        assert_eq!(editor.buf().cursor(), (3, 1));
+    }
+
+    #[test]
+    fn copy_to_clipboard_with_osc52() {
+        let input = DummyInputs(vec![ctrl('k'), alt('c'), alt('i')]);
+        let mut buf = Vec::new();
+        let mut editor =
+            Editor::with_lines(["abcd"].iter(), input, &mut buf, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        let msg = editor.screen().message_text();
+        assert!(msg.contains("Terminal clipboard cannot be read"), "{}", msg);
+        drop(editor);
+
+        let out = String::from_utf8(buf).unwrap();
+        assert!(out.contains("\x1b]52;c;YWJjZA==\x07"), "{:?}", out);
+    }
+
+    #[test]
+    fn deactivate_mark() {
+        let input = DummyInputs(vec![
+            ctrl(' '),
+            sp(EndKey),
+            ctrl(' '),
+            ctrl(' '), // Deactivate
+            key('x'),  // Editing text also deactivates mark
+            ctrl('q'),
+            ctrl('q'),
+        ]);
+        let mut editor =
+            Editor::with_lines(["abc"].iter(), input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(editor.buf().region(), None);
+        assert!(editor.bufs[0].hl.lines[0]
+            .iter()
+            .all(|hl| *hl == Highlight::Normal));
+    }
+
+    #[test]
+    fn undo_paste_at_once() {
+        let input = DummyInputs(vec![
+            sp(Paste("foo\n\tbar\nbaz".to_string())),
+            ctrl('u'), // Undo whole pasted text at once
+            ctrl('q'),
+        ]);
+        let mut editor =
+            Editor::with_lines(["abc"].iter(), input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["abc"]);
+        assert_eq!(editor.buf().cursor(), (0, 0));
+    }
+
+    #[test]
+    fn undo_insert_in_middle_of_line() {
+        let input = DummyInputs(vec![
+            ctrl('f'),
+            ctrl('f'),
+            sp(Paste("XYZ".to_string())),
+            ctrl('u'),
+        ]);
+        let mut editor =
+            Editor::with_lines(["abcd"].iter(), input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["abcd"]);
+        assert_eq!(editor.buf().cursor(), (2, 0));
+    }
+
+    #[test]
+    fn search_with_regex() {
+        let input = DummyInputs(vec![
+            ctrl('g'),
+            alt('r'), // Toggle regex mode
+            key('b'),
+            key('.'),
+            key('r'),
+            ctrl('n'), // Next match
+            key('\r'),
+        ]);
+        let mut editor = Editor::with_lines(
+            ["foo", "bar baz", "bxr"].iter(),
+            input,
+            Discard,
+            Some((80, 24)),
+        )
+        .unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(editor.buf().cursor(), (0, 2));
+        assert_eq!(editor.screen().message_text(), "Found");
+    }
+
+    #[test]
+    fn search_with_regex_over_lines() {
+        let input = DummyInputs(vec![
+            ctrl('g'),
+            alt('r'),
+            key('o'),
+            key('\\'),
+            key('n'),
+            key('b'),
+            key('\r'),
+        ]);
+        let mut editor =
+            Editor::with_lines(["foo", "bar"].iter(), input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(editor.buf().cursor(), (2, 0));
+        assert_eq!(editor.screen().message_text(), "Found");
+    }
+
+    #[test]
+    fn search_with_invalid_regex() {
+        let input = DummyInputs(vec![ctrl('g'), alt('r'), key('('), key('\r')]);
+        let mut buf = Vec::new();
+        let mut editor =
+            Editor::with_lines(["(foo)"].iter(), input, &mut buf, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(editor.screen().message_text(), "Not found");
+        drop(editor);
+        let out = String::from_utf8_lossy(&buf);
+        assert!(
+            out.contains("[Regex] Search: ( (Invalid regex: "),
+            "{:?}",
+            out
+        );
+    }
+
+    #[test]
+    fn search_ignoring_case() {
+        let input = DummyInputs(vec![
+            ctrl('g'),
+            alt('c'), // Ignore case
+            key('k'),
+            key('e'),
+            key('l'),
+            key('\r'),
+        ]);
+        // Kelvin sign is folded to 'k' though their lengths in UTF-8 are different
+        let mut editor = Editor::with_lines(
+            ["abc", "x \u{212a}ELVIN"].iter(),
+            input,
+            Discard,
+            Some((80, 24)),
+        )
+        .unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(editor.buf().cursor(), (2, 1));
+        assert_eq!(editor.screen().message_text(), "Found");
+    }
+
+    #[test]
+    fn search_with_smart_case() {
+        let input = DummyInputs(vec![
+            ctrl('g'),
+            alt('c'),
+            alt('c'), // Smart case
+            key('b'),
+            ctrl('n'), // Matches to 'B' since query is lower case
+            key('\r'),
+            ctrl('g'),
+            alt('c'),
+            alt('c'),
+            key('B'),
+            ctrl('n'), // Does not match to 'b' since query contains upper case
+            key('\r'),
+        ]);
+        let mut editor =
+            Editor::with_lines(["abc", "B b B"].iter(), input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(editor.buf().cursor(), (4, 1));
+    }
+
+    #[test]
+    fn query_replace() {
+        let input = DummyInputs(vec![
+            alt('%'),
+            key('a'),
+            key('b'),
+            key('\r'),
+            key('x'),
+            key('\r'),
+            key('y'), // Replace
+            key('n'), // Skip
+            key('!'), // Replace all rest
+        ]);
+        let mut editor = Editor::with_lines(
+            ["ab ab", "cab", "ab"].iter(),
+            input,
+            Discard,
+            Some((80, 24)),
+        )
+        .unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["x ab", "cx", "x"]);
+        assert_eq!(editor.screen().message_text(), "Replaced 3 occurrences");
+    }
+
+    #[test]
+    fn undo_query_replace_at_once() {
+        let input = DummyInputs(vec![
+            alt('%'),
+            key('a'),
+            key('\r'),
+            key('a'),
+            key('a'),
+            key('\r'),
+            key('y'),
+            key('y'), // Replaced text is not matched again
+            key('q'), // Quit
+            ctrl('u'),
+        ]);
+        let mut editor =
+            Editor::with_lines(["a a", "a"].iter(), input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["a a", "a"]);
+    }
+
+    #[test]
+    fn query_replace_with_regex_and_case() {
+        let input = DummyInputs(vec![
+            alt('%'),
+            alt('r'), // Toggle regex mode
+            alt('c'), // Ignore case
+            key('b'),
+            key('+'),
+            key('\r'),
+            key('x'),
+            key('\r'),
+            key('!'),
+        ]);
+        let mut editor =
+            Editor::with_lines(["aBb", "^", "b+"].iter(), input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["ax", "^", "x+"]);
+        assert_eq!(editor.screen().message_text(), "Replaced 2 occurrences");
+    }
+
+    #[test]
+    fn query_replace_invalid_regex() {
+        let input = DummyInputs(vec![alt('%'), alt('r'), key('('), key('\r')]);
+        let mut editor = Editor::with_lines(["("].iter(), input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["("]);
+        let msg = editor.screen().message_text();
+        assert!(msg.starts_with("Invalid regex: "), "{:?}", msg);
+    }
+
+    #[test]
+    fn query_replace_quit() {
+        let input = DummyInputs(vec![
+            alt('%'),
+            key('a'),
+            key('\r'),
+            key('\r'), // Replace with empty string
+            key('y'),
+            key('q'),
+        ]);
+        let mut editor =
+            Editor::with_lines(["a a", "a"].iter(), input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec![" a", "a"]);
+        assert_eq!(editor.screen().message_text(), "Replaced 1 occurrence");
+    }
+
+    #[test]
+    fn go_to_line() {
+        let input = DummyInputs(vec![
+            alt('g'),
+            key('5'),
+            key('0'),
+            key('\r'),
+            alt('g'),
+            key('-'),
+            key('9'),
+            key(':'),
+            key('2'),
+            key('\r'),
+        ]);
+        let lines = (1..=100).map(|i| format!("line {}", i));
+        let mut editor = Editor::with_lines(lines, input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(editor.buf().cursor(), (1, 40));
+        let rowoff = editor.screen().rowoff;
+        assert!(rowoff < 40 && 40 - rowoff >= 10, "{}", rowoff); // Centered
+    }
+
+    #[test]
+    fn cancel_go_to_line() {
+        let input = DummyInputs(vec![
+            sp(DownKey),
+            sp(EndKey),
+            alt('g'),
+            key('8'),
+            key('0'), // Preview line 80
+            ctrl('g'),
+        ]);
+        let lines = (1..=100).map(|i| format!("line {}", i));
+        let mut editor = Editor::with_lines(lines, input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(editor.buf().cursor(), (6, 1));
+        assert_eq!(editor.screen().rowoff, 0);
+    }
+
+    #[test]
+    fn go_to_invalid_line() {
+        let input = DummyInputs(vec![
+            alt('g'),
+            key('9'),
+            key('9'), // Clamped to the last line
+            key(':'),
+            key('9'), // Clamped to the end of line
+            key('\r'),
+            alt('g'),
+            key('x'),
+            key('\r'), // Cursor does not move
+        ]);
+        let mut editor =
+            Editor::with_lines(["abc", "def", "ghi"].iter(), input, Discard, Some((80, 24)))
+                .unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(editor.buf().cursor(), (3, 2));
+        let msg = editor.screen().message_text();
+        assert!(msg.contains("Invalid line number"), "{}", msg);
+    }
+
+    #[test]
+    fn open_file_at_line_and_column() {
+        let path = temp_file_path("open-at");
+        let text: String = (1..=100).map(|i| format!("line {}\n", i)).collect();
+        fs::write(&path, text).unwrap();
+
+        let arg = format!("{}:50:3", path.display());
+        let files = FileArg::parse_args(&[arg]).unwrap();
+        let input = DummyInputs(vec![]);
+        let mut editor = Editor::open_files(input, Discard, Some((80, 24)), &files, None).unwrap();
+        editor.edit().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        assert_eq!(editor.buf().cursor(), (2, 49));
+        let rowoff = editor.screen().rowoff;
+        assert!(rowoff < 49 && 49 - rowoff >= 10, "{}", rowoff); // Centered
+    }
+
+    #[test]
+    fn save_text_read_from_stdin() {
+        let path = temp_file_path("stdin");
+        let piped = TextBuffer::from_reader("foo\nbar\n".as_bytes()).unwrap();
+        let input = DummyInputs(vec![
+            ctrl('s'), // Prompt file name since the buffer is unnamed
+            sp(Paste(path.to_str().unwrap().to_string())),
+            key('\r'),
+        ]);
+        let mut editor =
+            Editor::open_files(input, Discard, Some((80, 24)), &[], Some(piped)).unwrap();
+        editor.edit().unwrap();
+
+        let saved = fs::read_to_string(&path).unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(saved, "foo\nbar\n");
+        assert_eq!(editor.bufs.len(), 1);
+    }
+
+    fn strip_escape_sequences(out: &[u8]) -> String {
+        let out = String::from_utf8_lossy(out);
+        let re = regex::Regex::new("\x1b\\[[0-9;?]*[a-zA-Z]").unwrap();
+        re.replace_all(&out, "").into_owned()
+    }
+
+    #[test]
+    fn render_absolute_line_numbers() {
+        let input = DummyInputs(vec![sp(DownKey)]);
+        let mut buf = Vec::new();
+        let lines = (1..=10).map(|i| format!("line{}", i));
+        let mut editor = Editor::with_lines(lines, input, &mut buf, Some((80, 24))).unwrap();
+        editor.set_line_numbers(LineNumbers::Absolute);
+        editor.edit().unwrap();
+        drop(editor);
+
+        let text = strip_escape_sequences(&buf);
+        assert!(text.contains(" 1 line1 2 line2"), "{:?}", text);
+        assert!(text.contains("10 line10"), "{:?}", text);
+        // Cursor is placed after the gutter
+        let out = String::from_utf8_lossy(&buf);
+        assert!(out.contains("\x1b[2;4H\x1b[?25h"), "{:?}", out);
+    }
+
+    #[test]
+    fn render_relative_line_numbers() {
+        let input = DummyInputs(vec![sp(DownKey), sp(DownKey)]);
+        let mut buf = Vec::new();
+        let mut editor = Editor::with_lines(
+            ["abc", "def", "ghi"].iter(),
+            input,
+            &mut buf,
+            Some((80, 24)),
+        )
+        .unwrap();
+        editor.set_line_numbers(LineNumbers::Relative);
+        editor.edit().unwrap();
+        drop(editor);
+
+        let text = strip_escape_sequences(&buf);
+        assert!(text.contains("2 abc1 def3 ghi"), "{:?}", text);
+    }
+
+    #[test]
+    fn render_soft_wrap() {
+        let input = DummyInputs(vec![]);
+        let mut buf = Vec::new();
+        let mut editor = Editor::with_lines(
+            ["0123456789abcdefghijあいう", "next"].iter(),
+            input,
+            &mut buf,
+            Some((10, 24)),
+        )
+        .unwrap();
+        editor.set_soft_wrap(true);
+        editor.edit().unwrap();
+        drop(editor);
+
+        let out = String::from_utf8_lossy(&buf);
+        // Double-width character which does not fit in the line goes to the next line
+        for (line, text) in [
+            (1, "0123456789"),
+            (2, "abcdefghij"),
+            (3, "あいう"),
+            (4, "next"),
+        ] {
+            let start = out.find(&format!("\x1b[{};1H", line)).unwrap();
+            let rest = strip_escape_sequences(out[start..].as_bytes());
+            assert!(rest.starts_with(text), "line {}: {:?}", line, rest);
+        }
+    }
+
+    #[test]
+    fn move_cursor_by_visual_line() {
+        let input = DummyInputs(vec![
+            sp(RightKey),
+            sp(DownKey),
+            key('x'),
+            sp(DownKey),
+            sp(DownKey),
+            key('y'),
+            sp(UpKey),
+            sp(UpKey),
+            key('z'),
+        ]);
+        let mut buf = Vec::new();
+        let mut editor = Editor::with_lines(
+            ["0123456789abcdefghijkl", "next"].iter(),
+            input,
+            &mut buf,
+            Some((10, 24)),
+        )
+        .unwrap();
+        editor.set_soft_wrap(true);
+        editor.edit().unwrap();
+
+        let lines = editor
+            .buf()
+            .rows()
+            .iter()
+            .map(|r| r.buffer())
+            .collect::<Vec<_>>();
+        // Column in visual line is kept while moving across rows
+        assert_eq!(lines, vec!["0123456789axbzcdefghijkl", "neyxt"]);
+        assert_eq!(editor.buf().cursor(), (14, 0));
+    }
+
+    #[test]
+    fn scroll_with_soft_wrap() {
+        let input = DummyInputs(vec![sp(DownKey); 8]);
+        let mut buf = Vec::new();
+        let lines = (0..5).map(|i| format!("{}", i).repeat(25));
+        let mut editor = Editor::with_lines(lines, input, &mut buf, Some((10, 7))).unwrap();
+        editor.set_soft_wrap(true);
+        editor.edit().unwrap();
+
+        // Each row takes 3 lines and 5 lines are available for text
+        assert_eq!(editor.buf().cursor(), (20, 2));
+        assert_eq!(editor.screen.rowoff, 2);
+    }
+
+    #[test]
+    fn edit_same_buffer_in_split_windows() {
+        let input = DummyInputs(vec![alt('2'), key('x'), alt('o'), key('y')]);
+        let mut buf = Vec::new();
+        let mut editor =
+            Editor::with_lines(["abc"].iter(), input, &mut buf, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(editor.screen().window_count(), 2);
+        assert_eq!(editor.screen().focused_window(), 1);
+        // Each window has its own cursor
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["yxabc"]);
+        assert_eq!(editor.buf().cursor(), (1, 0));
+        drop(editor);
+
+        // Lower window starts after status bar of upper window
+        let out = String::from_utf8_lossy(&buf);
+        let start = out.rfind("\x1b[12;1H").unwrap();
+        let rest = strip_escape_sequences(out[start..].as_bytes());
+        assert!(rest.starts_with("yxabc"), "{:?}", rest);
+    }
+
+    #[test]
+    fn render_vertical_split_windows() {
+        let input = DummyInputs(vec![alt('3')]);
+        let mut buf = Vec::new();
+        let mut editor =
+            Editor::with_lines(["abc"].iter(), input, &mut buf, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+        drop(editor);
+
+        let text = strip_escape_sequences(&buf);
+        let left = format!("abc{}|", " ".repeat(36));
+        assert!(text.contains(&left), "{:?}", text);
+        let out = String::from_utf8_lossy(&buf);
+        assert!(out.contains("\x1b[1;41H"), "{:?}", out);
+    }
+
+    #[test]
+    fn close_split_window() {
+        let input = DummyInputs(vec![alt('3'), alt('2'), alt('o'), alt('0'), alt('0')]);
+        let mut buf = Vec::new();
+        let mut editor =
+            Editor::with_lines(["abc"].iter(), input, &mut buf, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(editor.screen().window_count(), 1);
+        assert_eq!(editor.screen().focused_window(), 0);
+
+        let input = DummyInputs(vec![alt('0')]);
+        let mut editor =
+            Editor::with_lines(["abc"].iter(), input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(
+            editor.screen().message_text(),
+            "Cannot close the only window"
+        );
+    }
+
+    #[test]
+    fn split_window_showing_empty_buffer() {
+        let path = temp_file_path("split-empty");
+        fs::write(&path, "").unwrap();
+        let input = DummyInputs(vec![alt('2'), alt('o'), key('a'), alt('o')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.edit().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["a"]);
+    }
+
+    #[test]
+    fn restore_scroll_position_on_buffer_switch() {
+        let mut keys = vec![alt('>')];
+        keys.extend(vec![sp(UpKey); 10]);
+        keys.extend(vec![ctrl('o'), key('\r'), ctrl('x')]);
+        let input = DummyInputs(keys);
+        let lines = (1..=100).map(|i| format!("line{}", i));
+        let mut editor = Editor::with_lines(lines, input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+
+        assert_eq!(editor.buf_idx, 0);
+        // Scroll position is not reset to show the cursor at the bottom of screen
+        assert_eq!(editor.buf().cursor(), (0, 90));
+        assert_eq!(editor.screen().rowoff, 79);
+    }
+
+    #[test]
+    fn load_text_and_build_whole_text_once() {
+        let path = temp_file_path("whole-text");
+        fs::write(&path, "abc\r\n\tdef\r\nghi").unwrap();
+        let input = DummyInputs(vec![]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.edit().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let lines = editor.lines().collect::<Vec<_>>();
+        assert_eq!(lines, vec!["abc", "\tdef", "ghi"]);
+        let rendered = editor.buf().rows()[1].render_text();
+        assert_eq!(rendered, "        def");
+        assert_eq!(*editor.buf().text(), "abc\n\tdef\nghi\n");
+
+        // Text built for search is reused until the next modification
+        let text = editor.buf().text();
+        assert!(Rc::ptr_eq(&text, &editor.buf().text()));
+        editor.buf_mut().insert_char('x');
+        assert_eq!(*text, "abc\n\tdef\nghi\n");
+        assert_eq!(*editor.buf().text(), "xabc\n\tdef\nghi\n");
+        editor.buf_mut().undo();
+        assert_eq!(*editor.buf().text(), "abc\n\tdef\nghi\n");
+    }
+
+    #[test]
+    fn edit_lines_across_chunks() {
+        // Replace text between two ASCII positions of lines as TextBuffer does
+        fn replace(
+            lines: &mut Vec<String>,
+            (sx, sy): (usize, usize),
+            (ex, ey): (usize, usize),
+            s: &str,
+        ) {
+            let text = format!("{}{}{}", &lines[sy][..sx], s, &lines[ey][ex..]);
+            lines.splice(sy..=ey, text.split('\n').map(String::from));
+        }
+
+        let orig: Vec<_> = (0..3000).map(|i| format!("line {}", i)).collect();
+        let mut want = orig.clone();
+        let mut buf = TextBuffer::with_lines(orig.iter()).unwrap();
+
+        // Long insertions split chunks and removing many lines empties chunks
+        let long = format!("{}\nnew\n", "x".repeat(5000));
+        for y in (0..3000).step_by(300).rev() {
+            buf.set_cursor(2, y);
+            buf.insert_str(&long);
+            replace(&mut want, (2, y), (2, y), &long);
+        }
+        buf.replace_range((3, 10), (0, 2000), "\u{3042}\u{3044}");
+        replace(&mut want, (3, 10), (0, 2000), "\u{3042}\u{3044}");
+
+        assert_eq!(buf.lines().collect::<Vec<_>>(), want);
+        assert_eq!(buf.rows().len(), want.len());
+        assert_eq!(buf.rows()[10].len(), want[10].chars().count());
+        let text: String = want.iter().map(|l| format!("{}\n", l)).collect();
+        assert_eq!(*buf.text(), text);
+
+        while buf.undo() {}
+        assert_eq!(buf.lines().collect::<Vec<_>>(), orig);
+    }
+
+    #[test]
+    fn preserve_line_endings_on_save() {
+        let path = temp_file_path("crlf-noeol");
+        fs::write(&path, "abc\r\ndef").unwrap();
+        let input = DummyInputs(vec![key('x'), ctrl('s')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "xabc\r\ndef");
+        assert_eq!(editor.status_bar.right(), "plain UTF-8 CRLF noeol 1/2");
+
+        // Convert line endings and save. The conversion can be undone
+        let input = DummyInputs(vec![alt('e'), ctrl('s'), ctrl('u')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "xabc\ndef");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(editor.buf().line_ending(), LineEnding::CrLf);
+        assert!(editor.buf().modified());
+
+        let path = temp_file_path("lf-eol");
+        fs::write(&path, "abc\n\n").unwrap();
+        let input = DummyInputs(vec![key('x'), ctrl('s')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "xabc\n\n");
+        fs::remove_file(&path).unwrap();
+        assert_eq!(editor.status_bar.right(), "plain UTF-8 LF 1/2");
+    }
+
+    #[test]
+    fn preserve_mixed_line_endings_on_save() {
+        let tests: &[(&str, &[&str], &str)] = &[
+            (
+                "abc\ndef\r\nghi\n",
+                &["abc", "def\r", "ghi"],
+                "xabc\ndef\r\nghi\n",
+            ),
+            (
+                "abc\r\ndef\nghi\r\n",
+                &["abc\r", "def", "ghi\r"],
+                "xabc\r\ndef\nghi\r\n",
+            ),
+            ("abc\r", &["abc\r"], "xabc\r"),
+            ("abc\r\ndef\r", &["abc", "def\r"], "xabc\r\ndef\r"),
+        ];
+        let path = temp_file_path("mixed-eol");
+        for (text, lines, saved) in tests {
+            fs::write(&path, text).unwrap();
+            let input = DummyInputs(vec![key('x'), ctrl('s')]);
+            let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+            editor.edit().unwrap();
+            let mut want = lines.to_vec();
+            let first = format!("x{}", want[0]);
+            want[0] = &first;
+            assert_eq!(editor.lines().collect::<Vec<_>>(), want, "{:?}", text);
+            assert_eq!(fs::read_to_string(&path).unwrap(), *saved, "{:?}", text);
+        }
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn convert_mixed_line_endings() {
+        let path = temp_file_path("convert-mixed-eol");
+        fs::write(&path, "abc\ndef\r\n").unwrap();
+        let input = DummyInputs(vec![alt('e'), ctrl('s')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "abc\r\ndef\r\n");
+
+        // Converting back to LF does not leave CR
+        let input = DummyInputs(vec![alt('e'), ctrl('s')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "abc\ndef\n");
+        fs::write(&path, "abc\ndef\r\n").unwrap();
+
+        // Undo restores removed CR
+        let input = DummyInputs(vec![ctrl('n'), ctrl('e'), alt('e'), ctrl('u')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.edit().unwrap();
+        fs::remove_file(&path).unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["abc", "def\r"]);
+        assert_eq!(editor.buf().line_ending(), LineEnding::Lf);
+    }
+
+    #[test]
+    fn open_and_save_in_original_encoding() {
+        let path = temp_file_path("latin1");
+        fs::write(&path, b"caf\xe9\n").unwrap();
+        let input = DummyInputs(vec![key('x'), ctrl('s')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["xcafé"]);
+        assert_eq!(editor.status_bar.right(), "plain windows-1252 LF 1/1");
+        assert_eq!(fs::read(&path).unwrap(), b"xcaf\xe9\n");
+
+        // Character which cannot be encoded in the encoding is not saved
+        let input = DummyInputs(vec![utf8('あ'), ctrl('s')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), b"xcaf\xe9\n");
+        fs::remove_file(&path).unwrap();
+        assert!(editor.buf().modified());
+
+        let path = temp_file_path("utf8-bom");
+        fs::write(&path, b"\xef\xbb\xbfabc\n").unwrap();
+        let input = DummyInputs(vec![key('x'), ctrl('s')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["xabc"]);
+        assert_eq!(editor.status_bar.right(), "plain UTF-8 BOM LF 1/1");
+        assert_eq!(fs::read(&path).unwrap(), b"\xef\xbb\xbfxabc\n");
+        fs::remove_file(&path).unwrap();
+
+        let path = temp_file_path("utf16le-bom");
+        fs::write(&path, b"\xff\xfea\x00\r\x00\n\x00B0\r\x00\n\x00").unwrap();
+        let input = DummyInputs(vec![key('x'), ctrl('s')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["xa", "あ"]);
+        assert_eq!(editor.status_bar.right(), "plain UTF-16LE BOM CRLF 1/2");
+        let saved = b"\xff\xfex\x00a\x00\r\x00\n\x00B0\r\x00\n\x00";
+        assert_eq!(fs::read(&path).unwrap(), saved);
+        fs::remove_file(&path).unwrap();
+    }
+
+    #[test]
+    fn render_and_insert_control_chars() {
+        let path = temp_file_path("control-chars");
+        fs::write(&path, "a\x0cb\x1b\tc\n").unwrap();
+        let input = DummyInputs(vec![
+            sp(EndKey),
+            alt('q'),
+            ctrl('l'), // Inserted as \x0c
+            alt('q'),
+            ctrl('q'), // Quoted insert does not quit
+            alt('q'),
+            key('x'),
+        ]);
+        let mut buf = Vec::new();
+        let mut editor = Editor::open(input, &mut buf, Some((80, 24)), &[&path]).unwrap();
+        editor.edit().unwrap();
+        fs::remove_file(&path).unwrap();
+
+        let row = &editor.buf().rows()[0];
+        assert_eq!(row.buffer(), "a\x0cb\x1b\tc\x0c\x11x");
+        assert_eq!(row.render_text(), "a^Lb<1b>        c^L^Qx");
+        assert_eq!(editor.buf().cursor(), (9, 0));
+        assert_eq!(row.rx_from_cx(9), 22);
+
+        let hl = &editor.bufs[0].hl.lines[0];
+        let control = hl
+            .iter()
+            .enumerate()
+            .filter(|(_, hl)| **hl == Highlight::ControlChar)
+            .map(|(i, _)| i)
+            .collect::<Vec<_>>();
+        assert_eq!(control, vec![1, 2, 4, 5, 6, 7, 17, 18, 19, 20]);
+        drop(editor);
+
+        let out = String::from_utf8_lossy(&buf);
+        let start = out.find("\x1b[1;1H").unwrap();
+        let rest = strip_escape_sequences(out[start..].as_bytes());
+        assert!(rest.starts_with("a^Lb<1b>        c"), "{:?}", rest);
+    }
+
+    #[test]
+    fn quoted_newline_splits_line() {
+        let input = DummyInputs(vec![ctrl('f'), alt('q'), ctrl('j')]);
+        let mut editor =
+            Editor::with_lines(["abc"].iter(), input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["a", "bc"]);
+        assert_eq!(editor.buf().cursor(), (0, 1));
+    }
+
+    #[test]
+    fn paste_filters_control_chars() {
+        let input = DummyInputs(vec![sp(Paste("a\x1b[31mb\tc\x07\nd".to_string()))]);
+        let mut editor = Editor::with_lines([""].iter(), input, Discard, Some((80, 24))).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["a[31mb\tc", "d"]);
+    }
 
-#[cfg(test)]
-mod tests {
-    use crate::editor::Editor;
-    use crate::error::Result;
-    use crate::input::{InputSeq, KeySeq};
-    use crate::language::Language;
-    use std::fs::File;
-    use std::io::{self, BufRead, BufReader, Write};
+    #[test]
+    fn open_binary_file_in_hex_view() {
+        let path = temp_file_path("binary");
+        let bytes = b"\x7fELF\x00\x01\x02\x03abcdefghijkl";
+        fs::write(&path, bytes).unwrap();
+        let input = DummyInputs(vec![
+            ctrl('g'),
+            key('6'),
+            key('b'),
+            key(' '),
+            key('6'),
+            key('c'),
+            key('\r'),
+        ]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.edit().unwrap();
 
-    use KeySeq::*;
+        let lines = editor.lines().collect::<Vec<_>>();
+        let expected = vec![
+            "00000000  7f 45 4c 46 00 01 02 03  61 62 63 64 65 66 67 68  |.ELF....abcdefgh|",
+            "00000010  69 6a 6b 6c                                       |ijkl|",
+        ];
+        assert_eq!(lines, expected);
+        assert_eq!(editor.buf().cursor(), (16, 1)); // Hex digits of 0x12
+        assert_eq!(editor.status_bar.right(), "hex 2/2");
+        assert!(editor.status_bar.left().contains("(read-only)"));
+
+        // Edit and save are rejected. Go to offset 0x4 in hex
+        let input = DummyInputs(vec![
+            alt('g'),
+            key('0'),
+            key('x'),
+            key('4'),
+            key('\r'),
+            ctrl('s'),
+            key('x'),
+        ]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(fs::read(&path).unwrap(), bytes);
+        fs::remove_file(&path).unwrap();
 
-    struct DummyInputs(Vec<InputSeq>);
+        assert_eq!(editor.lines().collect::<Vec<_>>(), expected);
+        assert_eq!(editor.buf().cursor(), (22, 0));
+        assert!(!editor.buf().modified());
+        let msg = editor.screen().message_text();
+        assert!(msg.starts_with("Buffer is read-only"), "{:?}", msg);
+    }
 
-    impl Iterator for DummyInputs {
-        type Item = Result<InputSeq>;
+    #[test]
+    fn save_file_atomically() {
+        use std::os::unix::fs::{symlink, MetadataExt, PermissionsExt};
+
+        let dir = temp_file_path("atomic-save");
+        fs::create_dir(&dir).unwrap();
+        let target = dir.join("target.txt");
+        fs::write(&target, "abc\n").unwrap();
+        fs::set_permissions(&target, fs::Permissions::from_mode(0o600)).unwrap();
+        let link = dir.join("link.txt");
+        symlink(&target, &link).unwrap();
+
+        // Saving via symlink writes the real file and keeps its mode
+        let input = DummyInputs(vec![key('x'), ctrl('s')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&link]).unwrap();
+        editor.edit().unwrap();
+        assert!(!editor.buf().modified());
+        assert!(fs::symlink_metadata(&link)
+            .unwrap()
+            .file_type()
+            .is_symlink());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "xabc\n");
+        let mode = fs::metadata(&target).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        // Temporary file does not remain
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        // Stale temporary file left by crash does not prevent atomic save
+        let stale = dir.join(format!(".target.txt.kiro-{}.tmp", std::process::id()));
+        fs::write(&stale, "stale").unwrap();
+        let ino = fs::metadata(&target).unwrap().ino();
+        let input = DummyInputs(vec![key('x'), ctrl('s')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&target]).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "xxabc\n");
+        assert_ne!(fs::metadata(&target).unwrap().ino(), ino); // Replaced by rename
+        assert_eq!(fs::read_to_string(&stale).unwrap(), "stale");
+        fs::remove_file(&stale).unwrap();
+        assert_eq!(fs::read_dir(&dir).unwrap().count(), 2);
+
+        // Hard link is not broken
+        let hard = dir.join("hard.txt");
+        fs::hard_link(&target, &hard).unwrap();
+        let input = DummyInputs(vec![key('y'), ctrl('s')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&hard]).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(fs::read_to_string(&target).unwrap(), "yxxabc\n");
+        assert_eq!(fs::metadata(&hard).unwrap().nlink(), 2);
 
-        fn next(&mut self) -> Option<Self::Item> {
-            if self.0.is_empty() {
-                None
-            } else {
-                Some(Ok(self.0.remove(0)))
-            }
-        }
+        fs::remove_dir_all(&dir).unwrap();
     }
 
-    struct Discard;
+    #[test]
+    fn detect_file_changed_on_disk() {
+        let path = temp_file_path("changed-on-disk");
+        fs::write(&path, "abc\n").unwrap();
+
+        // Unmodified buffer is reloaded when terminal gets focus
+        let input = DummyInputs(vec![sp(FocusIn)]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        fs::write(&path, "external\n").unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["external"]);
+        assert!(!editor.buf().modified());
 
-    impl Write for Discard {
-        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
-            Ok(buf.len())
-        }
+        // Modified buffer is not reloaded. User chooses overwriting the file
+        let input = DummyInputs(vec![key('x'), sp(FocusIn)]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        fs::write(&path, "again\n").unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["xexternal"]);
+        let msg = editor.screen().message_text();
+        assert!(msg.contains("changed on disk"), "{:?}", msg);
 
-        fn flush(&mut self) -> io::Result<()> {
-            Ok(())
-        }
-    }
+        // Saving requires confirmation
+        let input = DummyInputs(vec![key('x'), ctrl('s')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        fs::write(&path, "overwritten\n").unwrap();
+        editor.edit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "overwritten\n");
+        assert!(editor.buf().modified());
 
-    fn key(c: char) -> InputSeq {
-        InputSeq::new(Key(c as u8))
-    }
+        let input = DummyInputs(vec![key('x'), ctrl('s'), ctrl('s')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        fs::write(&path, "again\n").unwrap();
+        editor.edit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "xoverwritten\n");
+        assert!(!editor.buf().modified());
 
-    fn ctrl(c: char) -> InputSeq {
-        InputSeq::ctrl(Key(c as u8))
-    }
+        // Reload discards changes after confirmation
+        let input = DummyInputs(vec![key('y'), alt('r'), alt('r')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        fs::write(&path, "reloaded\n").unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["reloaded"]);
+        assert!(!editor.buf().modified());
 
-    fn sp(k: KeySeq) -> InputSeq {
-        if let Key(_) = k {
-            panic!("{:?}", k);
-        }
-        InputSeq::new(k)
+        fs::remove_file(&path).unwrap();
     }
 
-    fn utf8(c: char) -> InputSeq {
-        InputSeq::new(Utf8Key(c))
+    // Header of swap file left by a session on another machine. It is always leftover
+    const CRASHED_SWAP: &str = "kiro-swp 1 crashed-host\n";
+
+    fn swap_text(path: &Path) -> String {
+        crate::swap::read(path).unwrap().text
     }
 
     #[test]
-    fn empty_buffer() {
-        let input = DummyInputs(vec![InputSeq::ctrl(Key(b'q'))]);
-        let mut editor = Editor::new(input, Discard, Some((80, 24))).unwrap();
+    fn recover_from_swap_file() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let path = temp_file_path("swap");
+        let swap = env::temp_dir().join(format!(
+            ".{}.kiro-swp",
+            path.file_name().unwrap().to_str().unwrap()
+        ));
+        fs::write(&path, "abc\ndef\n").unwrap();
+
+        // Swap file is written on the first modification, updated after some edits and removed
+        // on save
+        let mut keys = vec![key('x'); SWAP_UPDATE_COUNT + 1];
+        keys.push(ctrl('s'));
+        let mut editor =
+            Editor::open(DummyInputs(keys), Discard, Some((80, 24)), &[&path]).unwrap();
+        let mut edit = editor.first_paint().unwrap();
+        edit.next().unwrap().unwrap();
+        assert_eq!(swap_text(&path), "xabc\ndef\n");
+        let mode = fs::metadata(&swap).unwrap().permissions().mode();
+        assert_eq!(mode & 0o777, 0o600);
+        for _ in 0..SWAP_UPDATE_COUNT {
+            edit.next().unwrap().unwrap();
+        }
+        let want = "x".repeat(SWAP_UPDATE_COUNT + 1);
+        assert!(swap_text(&path).starts_with(&want));
+        edit.next().unwrap().unwrap();
+        assert!(!swap.exists());
+        fs::write(&path, "abc\ndef\n").unwrap();
+
+        // Leftover swap file is recovered
+        fs::write(&swap, format!("{}abc\nfoo\nbar\n", CRASHED_SWAP)).unwrap();
+        let input = DummyInputs(vec![key('y')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.first_paint().unwrap();
+        let msg = editor.screen().message_text();
+        assert!(msg.contains("(+2 -1 lines)"), "{:?}", msg);
         editor.edit().unwrap();
+        assert_eq!(
+            editor.lines().collect::<Vec<_>>(),
+            vec!["abc", "foo", "bar"]
+        );
+        assert!(editor.buf().modified());
+        assert!(swap.exists());
 
-        assert!(editor.screen().rows() > 0);
-        assert!(editor.screen().cols() > 0);
-        assert_eq!(editor.lines().collect::<Vec<_>>(), vec![""]);
+        // Leftover swap file is deleted
+        let input = DummyInputs(vec![key('n')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["abc", "def"]);
+        assert!(!swap.exists());
 
-        let msg = editor.screen().message_text();
-        assert_eq!(msg, "Ctrl-? for help");
+        // Swap file written in this session is removed on quit
+        let input = DummyInputs(vec![key('a'), ctrl('q'), ctrl('q')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["aabc", "def"]);
+        assert!(!swap.exists());
+
+        // Leftover swap file is neither overwritten nor removed until user answers recovering it
+        let leftover = format!("{}foo\n", CRASHED_SWAP);
+        fs::write(&swap, &leftover).unwrap();
+        let input = DummyInputs(vec![key('a'), key('b'), ctrl('q'), ctrl('q')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["ababc", "def"]);
+        assert_eq!(fs::read_to_string(&swap).unwrap(), leftover);
+
+        let input = DummyInputs(vec![key('a'), ctrl('q')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(fs::read_to_string(&swap).unwrap(), leftover);
+        fs::remove_file(&swap).unwrap();
+
+        fs::remove_file(&path).unwrap();
     }
 
     #[test]
-    fn write_to_empty_buffer() {
-        let input = DummyInputs(vec![key('a'), key('b'), key('c'), ctrl('q'), ctrl('q')]);
-        let mut editor = Editor::new(input, Discard, Some((80, 24))).unwrap();
+    fn swap_file_is_not_followed_as_symlink() {
+        use crate::swap;
+
+        let path = temp_file_path("swap-symlink");
+        let target = temp_file_path("swap-symlink-target");
+        fs::write(&path, "abc\n").unwrap();
+        fs::write(&target, "secret\n").unwrap();
+        std::os::unix::fs::symlink(&target, swap::path_of(&path)).unwrap();
+
+        // Symlink is neither offered as leftover nor written through
+        let input = DummyInputs(vec![key('x')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
         editor.edit().unwrap();
+        assert!(!editor.screen().message_text().contains("Recover"));
+        assert_eq!(fs::read_to_string(&target).unwrap(), "secret\n");
+
+        // Symlink was removed as invalid swap file and swap file of this session was written
+        assert_eq!(swap_text(&path), "xabc\n");
+        fs::remove_file(swap::path_of(&path)).unwrap();
+        fs::write(&target, "abc\n").unwrap();
+        std::os::unix::fs::symlink(&target, swap::path_of(&path)).unwrap();
+        assert!(swap::read(&path).is_none());
+        assert!(swap::write(&path, "xabc\n").is_err());
+        assert_eq!(fs::read_to_string(&target).unwrap(), "abc\n");
+
+        fs::remove_file(swap::path_of(&path)).unwrap();
+        fs::remove_file(&target).unwrap();
+        fs::remove_file(&path).unwrap();
+    }
 
-        let lines = editor.lines().collect::<Vec<_>>();
-        assert_eq!(lines, vec!["abc"]);
-
+    #[test]
+    fn swap_file_of_running_process_is_kept() {
+        use crate::swap;
+        use std::os::unix::process::parent_id;
+
+        let path = temp_file_path("swap-running");
+        fs::write(&path, "abc\n").unwrap();
+        let written = swap::path_of(&path);
+        swap::write(&path, "foo\n").unwrap();
+        let mut content = fs::read_to_string(&written).unwrap();
+        // Pretend that the parent process is another kiro editing the file
+        content = content.replacen(&process::id().to_string(), &parent_id().to_string(), 1);
+        fs::write(&written, &content).unwrap();
+
+        let input = DummyInputs(vec![key('x'), ctrl('q'), ctrl('q')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.first_paint().unwrap();
         let msg = editor.screen().message_text();
         assert!(
-            msg.contains("At least one file has unsaved changes!"),
-            "{}",
+            msg.contains("is being edited by another kiro process"),
+            "{:?}",
             msg
         );
+        editor.edit().unwrap();
+        assert_eq!(fs::read_to_string(&written).unwrap(), content);
+
+        fs::remove_file(&written).unwrap();
+        fs::remove_file(&path).unwrap();
     }
 
     #[test]
-    fn edit_step_by_step() {
-        let keys = vec![key('a'), key('b'), key('c'), ctrl('q'), ctrl('q')];
-        let input = DummyInputs(keys.clone());
-        let mut editor = Editor::new(input, Discard, Some((80, 24))).unwrap();
-        let mut editing = editor.first_paint().unwrap();
-
-        let mut keys = keys.iter();
-        let mut xs = [1, 2, 3, 3].iter();
-        while let Some(res) = editing.next() {
-            let key = res.unwrap();
-            let (x, y) = editing.editor().buf().cursor();
-            assert_eq!(y, 0);
-            assert_eq!(*xs.next().unwrap(), x);
-            assert_eq!(keys.next().unwrap(), &key);
+    fn autosave_reports_all_unsaved_buffers() {
+        use crate::swap;
+
+        let paths: Vec<_> = (1..=3)
+            .map(|i| temp_file_path(&format!("autosave-skip{}", i)))
+            .collect();
+        for path in paths.iter() {
+            fs::write(path, "abc\n").unwrap();
         }
 
-        let mut lines = editor.lines();
-        assert_eq!(lines.len(), 1);
-        let line = lines.next().unwrap();
-        assert_eq!(line, "abc");
-    }
-
-    #[test]
-    fn move_cursor_down() {
+        // The first buffer changed on disk does not prevent saving the others. The second buffer
+        // was already saved on switching to the third buffer
         let input = DummyInputs(vec![
-            key('a'),
-            sp(DownKey),
-            key('b'),
-            sp(DownKey),
-            key('c'),
-            ctrl('q'),
-            ctrl('q'),
+            key('x'),
+            ctrl('x'),
+            key('y'),
+            ctrl('x'),
+            key('z'),
+            sp(Idle),
         ]);
-        let mut editor = Editor::new(input, Discard, Some((80, 24))).unwrap();
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &paths).unwrap();
+        editor.set_autosave(true);
+        fs::write(&paths[0], "external\n").unwrap();
         editor.edit().unwrap();
 
-        assert!(editor.screen().rows() > 0);
-        assert!(editor.screen().cols() > 0);
+        assert_eq!(fs::read_to_string(&paths[0]).unwrap(), "external\n");
+        assert_eq!(fs::read_to_string(&paths[1]).unwrap(), "yabc\n");
+        assert_eq!(fs::read_to_string(&paths[2]).unwrap(), "zabc\n");
+        let want = format!(
+            "Autosaved 1 file(s), skipped {} changed on disk",
+            paths[0].to_str().unwrap(),
+        );
+        assert_eq!(editor.screen().message_text(), want);
 
-        let lines = editor.lines().collect::<Vec<_>>();
-        assert_eq!(lines, vec!["a", "b", "c"]);
+        for path in paths.iter() {
+            fs::remove_file(path).unwrap();
+            let _ = fs::remove_file(swap::path_of(path));
+        }
     }
 
     #[test]
-    fn open_file() {
-        let input = DummyInputs(vec![ctrl('q')]);
-
-        let this_file = file!();
-        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[this_file]).unwrap();
+    fn autosave_on_idle_and_buffer_switch() {
+        let path1 = temp_file_path("autosave1");
+        let path2 = temp_file_path("autosave2");
+        fs::write(&path1, "abc\n").unwrap();
+        fs::write(&path2, "def\n").unwrap();
+
+        let input = DummyInputs(vec![key('x'), ctrl('x'), key('y'), sp(Idle)]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path1, &path2]).unwrap();
+        editor.set_autosave(true);
         editor.edit().unwrap();
-
-        let f = BufReader::new(File::open(this_file).unwrap());
-        for (i, (expected, actual)) in f.lines().zip(editor.lines()).enumerate() {
-            assert_eq!(expected.unwrap(), actual, "Line: {}", i + 1);
-        }
-
-        assert_eq!(editor.lang(), Language::Rust);
+        assert_eq!(fs::read_to_string(&path1).unwrap(), "xabc\n");
+        assert_eq!(fs::read_to_string(&path2).unwrap(), "ydef\n");
+        assert!(!editor.bufs.iter().any(|b| b.text.modified()));
+        assert_eq!(editor.screen().message_text(), "Autosaved 1 file(s)");
+
+        // Without autosave, only swap file is written on idle
+        let input = DummyInputs(vec![key('z'), sp(Idle)]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path1]).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(fs::read_to_string(&path1).unwrap(), "xabc\n");
+        assert!(editor.buf().modified());
+        let swap = env::temp_dir().join(format!(
+            ".{}.kiro-swp",
+            path1.file_name().unwrap().to_str().unwrap()
+        ));
+        assert_eq!(swap_text(&path1), "zxabc\n");
+
+        fs::remove_file(&swap).unwrap();
+        fs::remove_file(&path1).unwrap();
+        fs::remove_file(&path2).unwrap();
     }
 
     #[test]
-    fn message_bar_squashed() {
-        let input = DummyInputs(vec![ctrl('l'), sp(Unidentified), ctrl('q')]);
-        let mut buf = Vec::new();
-        let mut editor = Editor::new(input, &mut buf, Some((80, 24))).unwrap();
+    fn backup_file_on_first_save() {
+        let dir = temp_file_path("backup");
+        fs::create_dir(&dir).unwrap();
+        let path = dir.join("file.txt");
+        fs::write(&path, "abc\n").unwrap();
+
+        // Only the original file is backed up
+        let input = DummyInputs(vec![key('x'), ctrl('s'), key('y'), ctrl('s')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.set_backup(Backup {
+            mode: BackupMode::Single,
+            dir: None,
+        });
         editor.edit().unwrap();
-
+        assert_eq!(fs::read_to_string(&path).unwrap(), "xyabc\n");
+        assert_eq!(fs::read_to_string(dir.join("file.txt~")).unwrap(), "abc\n");
+
+        // Numbered backup takes the next number of existing backups
+        fs::write(dir.join("file.txt.~3~"), "old\n").unwrap();
+        let input = DummyInputs(vec![key('z'), ctrl('s')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.set_backup(Backup {
+            mode: BackupMode::Numbered,
+            dir: None,
+        });
+        editor.edit().unwrap();
+        let backup = fs::read_to_string(dir.join("file.txt.~4~")).unwrap();
+        assert_eq!(backup, "xyabc\n");
+
+        // Backup directory
+        let backup_dir = dir.join("backups");
+        let input = DummyInputs(vec![key('w'), ctrl('s')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.set_backup(Backup {
+            mode: BackupMode::Single,
+            dir: Some(backup_dir.clone()),
+        });
+        editor.edit().unwrap();
+        let name = format!("{}~", fs::canonicalize(&path).unwrap().display()).replace('/', "!");
+        let backup = fs::read_to_string(backup_dir.join(name)).unwrap();
+        assert_eq!(backup, "zxyabc\n");
+
+        // Failure of backup aborts saving
+        let input = DummyInputs(vec![key('v'), ctrl('s')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.set_backup(Backup {
+            mode: BackupMode::Single,
+            dir: Some(path.clone()), // Not a directory
+        });
+        editor.edit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "wzxyabc\n");
+        assert!(editor.buf().modified());
         let msg = editor.screen().message_text();
-        assert_eq!(msg, "");
+        assert!(
+            msg.starts_with("Could not save: Backup failed"),
+            "{:?}",
+            msg
+        );
+
+        // Reloading the file does not make another backup in the same session
+        let input = DummyInputs(vec![key('u'), ctrl('s'), alt('r'), key('t'), ctrl('s')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path]).unwrap();
+        editor.set_backup(Backup {
+            mode: BackupMode::Numbered,
+            dir: None,
+        });
+        editor.edit().unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "utwzxyabc\n");
+        let backup = fs::read_to_string(dir.join("file.txt.~5~")).unwrap();
+        assert_eq!(backup, "wzxyabc\n");
+        assert!(!dir.join("file.txt.~6~").exists());
+
+        fs::remove_dir_all(&dir).unwrap();
     }
 
     #[test]
-    fn undo_modified() {
-        let input = DummyInputs(vec![
-            key('a'),
-            key('b'),
-            key('c'),
-            ctrl('m'),
-            ctrl('u'),
-            ctrl('u'),
-            ctrl('q'),
-            ctrl('q'),
-        ]);
-        let mut editor = Editor::new(input, Discard, Some((80, 24))).unwrap();
+    fn close_buffer() {
+        use crate::swap;
+
+        let path1 = temp_file_path("close-buffer1");
+        let path2 = temp_file_path("close-buffer2");
+        fs::write(&path1, "abc\n").unwrap();
+        fs::write(&path2, "def\n").unwrap();
+
+        // Other window showing the closed buffer shows the next buffer
+        let input = DummyInputs(vec![alt('2'), alt('k')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path1, &path2]).unwrap();
         editor.edit().unwrap();
+        assert_eq!(editor.bufs.len(), 1);
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["def"]);
+        assert!(editor.windows.iter().all(|w| w.buf_idx == 0));
 
-        let lines = editor.lines().collect::<Vec<_>>();
-        assert_eq!(lines, vec![""]);
+        // Modified buffer requires confirmation. Closing the last buffer opens empty buffer
+        let input = DummyInputs(vec![alt('x'), key('x'), alt('k')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path1, &path2]).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.bufs.len(), 2);
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec!["xdef"]);
+
+        let input = DummyInputs(vec![key('x'), alt('k'), alt('k'), alt('k')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path1]).unwrap();
+        editor.edit().unwrap();
+        assert_eq!(editor.bufs.len(), 1);
+        assert!(!editor.buf().has_file());
+        assert!(!editor.buf().modified());
+        assert_eq!(editor.lines().collect::<Vec<_>>(), vec![""]);
+        assert_eq!(fs::read_to_string(&path1).unwrap(), "abc\n");
+        assert!(!swap::path_of(&path1).exists()); // Swap file of discarded changes is removed
+
+        // Leftover swap file which is not answered yet is kept on closing the buffer
+        let leftover = format!("{}foo\n", CRASHED_SWAP);
+        fs::write(swap::path_of(&path1), &leftover).unwrap();
+        let input = DummyInputs(vec![alt('k')]);
+        let mut editor = Editor::open(input, Discard, Some((80, 24)), &[&path1]).unwrap();
+        editor.edit().unwrap();
+        assert!(!editor.buf().has_file());
+        assert_eq!(fs::read_to_string(swap::path_of(&path1)).unwrap(), leftover);
 
-        assert!(!editor.bufs[0].modified());
+        fs::remove_file(swap::path_of(&path1)).unwrap();
+        fs::remove_file(swap::path_of(&path2)).unwrap();
+        fs::remove_file(&path1).unwrap();
+        fs::remove_file(&path2).unwrap();
     }
 
     macro_rules! test_text_edit {
@@ -1282,4 +3510,136 @@ s",
             cursor: (0, 4),
         }
     );
+
+    test_text_edit!(
+        delete_region,
+        delete_region_undo,
+        delete_region_redo {
+            before: "
+abc
+def
+ghi",
+            input: [
+                sp(RightKey),
+                ctrl(' '),
+                sp(DownKey),
+                ctrl('w'), // Delete 'bc\nd'
+                sp(DownKey),
+                ctrl(' '),
+                sp(LeftKey),
+                sp(UpKey),
+                ctrl('w'), // Delete 'aef\ng' (mark is after cursor)
+                ctrl(' '),
+                ctrl('w'), // Delete nothing with empty region
+                sp(RightKey),
+                ctrl('w'), // Delete a word without mark
+            ],
+            after: "
+i",
+            cursor: (0, 0),
+        }
+    );
+
+    test_text_edit!(
+        indent_region,
+        indent_region_undo,
+        indent_region_redo {
+            before: "
+ab
+
+cd
+ef",
+            input: [
+                sp(RightKey),
+                ctrl(' '),
+                sp(DownKey),
+                sp(DownKey),
+                ctrl('i'), // Region ending at head of line does not include the line
+                sp(DownKey),
+                ctrl(' '),
+                sp(UpKey),
+                ctrl('i'), // Indent 'cd'
+                sp(UpKey),
+                ctrl(' '),
+                sp(UpKey),
+                sp(UpKey),
+                ctrl('i'), // Indent 'ab' again. Cursor follows the indented text
+            ],
+            after: "
+		ab
+
+	cd
+ef",
+            cursor: (1, 0),
+        }
+    );
+
+    test_text_edit!(
+        kill_and_yank,
+        kill_and_yank_undo,
+        kill_and_yank_redo {
+            before: "
+abc def
+ghi",
+            input: [
+                ctrl('k'), // Cut 'abc def'
+                ctrl('k'), // Cut newline. It is appended to the previous cut
+                ctrl('y'), // Paste 'abc def\n'
+                ctrl('y'), // Paste again
+            ],
+            after: "
+abc def
+abc def
+ghi",
+            cursor: (0, 2),
+        }
+    );
+
+    test_text_edit!(
+        yank_pop,
+        yank_pop_undo,
+        yank_pop_redo {
+            before: "
+foo bar
+baz",
+            input: [
+                sp(EndKey),
+                ctrl('w'), // Cut 'bar'
+                ctrl('w'), // Cut 'foo '. It is prepended to the previous cut
+                sp(DownKey),
+                sp(EndKey),
+                ctrl('j'), // Cut 'baz' as new entry
+                ctrl('y'), // Paste 'baz'
+                alt('y'),  // Replace with 'foo bar'
+                alt('y'),  // Cycle back to 'baz'
+                alt('y'),  // Replace with 'foo bar' again
+            ],
+            after: "
+
+foo bar",
+            cursor: (7, 1),
+        }
+    );
+
+    test_text_edit!(
+        paste,
+        paste_undo,
+        paste_redo {
+            before: "
+abc
+def",
+            input: [
+                sp(RightKey),
+                sp(Paste("12\n	34\n".to_string())), // Tab is inserted as-is
+                sp(DownKey),
+                sp(Paste("\u{3042}".to_string())),
+            ],
+            after: "
+a12
+	34
+bc
+\u{3042}def",
+            cursor: (1, 3),
+        }
+    );
 }
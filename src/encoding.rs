@@ -0,0 +1,97 @@
+use encoding_rs::{Encoding, UTF_16BE, UTF_16LE, UTF_8, WINDOWS_1252};
+use std::borrow::Cow;
+use std::fmt;
+
+// Encoding of file. Text is converted into UTF-8 on loading and converted back on saving
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FileEncoding {
+    encoding: &'static Encoding,
+    bom: bool,
+}
+
+impl Default for FileEncoding {
+    fn default() -> Self {
+        Self {
+            encoding: UTF_8,
+            bom: false,
+        }
+    }
+}
+
+impl FileEncoding {
+    // Detect encoding of the bytes and decode them into UTF-8 text. Encoding is detected by BOM
+    // at first. When the bytes are not valid UTF-8, they are decoded with single byte encoding
+    // which can decode any bytes
+    pub fn decode(bytes: Vec<u8>) -> (String, Self) {
+        if let Some((encoding, len)) = Encoding::for_bom(&bytes) {
+            let decoded =
+                encoding.decode_without_bom_handling_and_without_replacement(&bytes[len..]);
+            if let Some(text) = decoded {
+                return (
+                    text.into_owned(),
+                    Self {
+                        encoding,
+                        bom: true,
+                    },
+                );
+            }
+        }
+
+        match String::from_utf8(bytes) {
+            Ok(text) => (text, Self::default()),
+            Err(err) => {
+                let bytes = err.into_bytes();
+                let (text, _) = WINDOWS_1252.decode_without_bom_handling(&bytes);
+                let encoding = Self {
+                    encoding: WINDOWS_1252,
+                    bom: false,
+                };
+                (text.into_owned(), encoding)
+            }
+        }
+    }
+
+    // Encode UTF-8 text into bytes of this encoding. Returns None when the text contains some
+    // character which cannot be represented in this encoding
+    pub fn encode<'a>(&self, text: &'a str) -> Option<Cow<'a, [u8]>> {
+        // encoding_rs does not provide encoders for UTF-16
+        if self.encoding == UTF_16LE {
+            let bytes = text.encode_utf16().flat_map(u16::to_le_bytes).collect();
+            return Some(Cow::Owned(bytes));
+        }
+        if self.encoding == UTF_16BE {
+            let bytes = text.encode_utf16().flat_map(u16::to_be_bytes).collect();
+            return Some(Cow::Owned(bytes));
+        }
+        let (bytes, _, unmappable) = self.encoding.encode(text);
+        if unmappable {
+            None
+        } else {
+            Some(bytes)
+        }
+    }
+
+    // BOM written at head of file
+    pub fn bom(&self) -> &'static [u8] {
+        if !self.bom {
+            return b"";
+        }
+        if self.encoding == UTF_16LE {
+            b"\xff\xfe"
+        } else if self.encoding == UTF_16BE {
+            b"\xfe\xff"
+        } else {
+            b"\xef\xbb\xbf"
+        }
+    }
+}
+
+impl fmt::Display for FileEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.encoding.name())?;
+        if self.bom {
+            write!(f, " BOM")?;
+        }
+        Ok(())
+    }
+}
@@ -10,7 +10,8 @@ pub enum Error {
     TooSmallWindow(usize, usize),
     UnknownWindowSize,
     NotUtf8Input(Vec<u8>),
-    ControlCharInText(char),
+    ClipboardCommandFailed(String, String),
+    ClipboardNotReadable,
 }
 
 impl fmt::Display for Error {
@@ -32,7 +33,13 @@ impl fmt::Display for Error {
                 }
                 Ok(())
             }
-            ControlCharInText(c) => write!(f, "Invalid character for text is included: {:?}", c),
+            ClipboardCommandFailed(cmd, reason) => {
+                write!(f, "Clipboard command {:?} failed: {}", cmd, reason)
+            }
+            ClipboardNotReadable => write!(
+                f,
+                "Terminal clipboard cannot be read. Please specify command with --paste-command"
+            ),
         }
     }
 }
@@ -0,0 +1,133 @@
+use std::path::Path;
+
+// File given from command line with initial cursor position. Line and column are 1-based
+#[derive(PartialEq, Debug)]
+pub struct FileArg {
+    pub path: String,
+    pub line: Option<usize>,
+    pub col: Option<usize>,
+}
+
+impl FileArg {
+    // Parse 'path', 'path:line' or 'path:line:col'. Compilers report positions in this format.
+    // When the whole argument is an existing file path, it is not parsed.
+    pub fn parse(arg: &str) -> FileArg {
+        let whole = FileArg {
+            path: arg.to_string(),
+            line: None,
+            col: None,
+        };
+        if Path::new(arg).exists() {
+            return whole;
+        }
+
+        fn split_num(s: &str) -> Option<(&str, usize)> {
+            let (rest, num) = s.rsplit_once(':')?;
+            if rest.is_empty() {
+                return None;
+            }
+            Some((rest, num.parse().ok()?))
+        }
+
+        match split_num(arg) {
+            Some((rest, last)) => match split_num(rest) {
+                Some((path, line)) => FileArg {
+                    path: path.to_string(),
+                    line: Some(line),
+                    col: Some(last),
+                },
+                None => FileArg {
+                    path: rest.to_string(),
+                    line: Some(last),
+                    col: None,
+                },
+            },
+            None => whole,
+        }
+    }
+
+    // Parse free arguments of command line. '+N' before a path means the line number of the file
+    // like vi and nano. Returns error message when '+N' is not followed by a path.
+    pub fn parse_args<S: AsRef<str>>(args: &[S]) -> Result<Vec<FileArg>, String> {
+        let mut files = vec![];
+        let mut line = None;
+        for arg in args {
+            let arg = arg.as_ref();
+            if let Some(n) = arg.strip_prefix('+').and_then(|n| n.parse().ok()) {
+                line = Some((arg, n));
+                continue;
+            }
+            let mut file = FileArg::parse(arg);
+            if let Some((_, n)) = line.take() {
+                file.line = Some(n);
+            }
+            files.push(file);
+        }
+        match line {
+            Some((arg, _)) => Err(format!("No file path is given after {:?}", arg)),
+            None => Ok(files),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::env;
+    use std::fs;
+    use std::process;
+
+    #[test]
+    fn parse_file_args() {
+        // Existing file is not parsed even if its name looks like 'path:line'
+        let existing = env::temp_dir().join(format!("kiro-test-file-arg-{}:3", process::id()));
+        fs::write(&existing, "").unwrap();
+        let existing = existing.to_str().unwrap().to_string();
+
+        let args = [
+            "foo.rs:12:3",
+            "+7",
+            "bar.rs",
+            "baz.rs:4",
+            "a:b",
+            existing.as_str(),
+        ];
+        let files = FileArg::parse_args(&args);
+        fs::remove_file(&existing).unwrap();
+
+        let want = vec![
+            FileArg {
+                path: "foo.rs".to_string(),
+                line: Some(12),
+                col: Some(3),
+            },
+            FileArg {
+                path: "bar.rs".to_string(),
+                line: Some(7),
+                col: None,
+            },
+            FileArg {
+                path: "baz.rs".to_string(),
+                line: Some(4),
+                col: None,
+            },
+            FileArg {
+                path: "a:b".to_string(),
+                line: None,
+                col: None,
+            },
+            FileArg {
+                path: existing.clone(),
+                line: None,
+                col: None,
+            },
+        ];
+        assert_eq!(files, Ok(want));
+    }
+
+    #[test]
+    fn line_arg_without_file() {
+        let err = FileArg::parse_args(&["foo.rs", "+7"]).unwrap_err();
+        assert_eq!(err, "No file path is given after \"+7\"");
+    }
+}
@@ -0,0 +1,96 @@
+use encoding_rs::Encoding;
+use std::fmt::Write;
+
+// Binary file is shown in hex view. Each line shows offset, hex bytes and ASCII characters:
+//
+//   00000000  7f 45 4c 46 02 01 01 00  00 00 00 00 00 00 00 00  |.ELF............|
+//
+const BYTES_PER_LINE: usize = 16;
+const HEX_START: usize = 10; // Offset (8 chars) and 2 spaces
+
+// Same heuristic as Git. Text file rarely contains NUL byte. UTF-16 text file is excluded by
+// its BOM since it contains NUL bytes for ASCII characters.
+pub fn is_binary(bytes: &[u8]) -> bool {
+    let head = &bytes[..bytes.len().min(8000)];
+    head.contains(&0) && Encoding::for_bom(head).is_none()
+}
+
+// Hex dump of the bytes. Each line ends with newline
+pub fn dump(bytes: &[u8]) -> String {
+    let num_lines = bytes.len().div_ceil(BYTES_PER_LINE);
+    let mut text = String::with_capacity(num_lines * 78);
+    for (y, line) in bytes.chunks(BYTES_PER_LINE).enumerate() {
+        write!(text, "{:08x} ", y * BYTES_PER_LINE).unwrap();
+        for i in 0..BYTES_PER_LINE {
+            if i % 8 == 0 {
+                text.push(' ');
+            }
+            match line.get(i) {
+                Some(b) => write!(text, "{:02x} ", b).unwrap(),
+                None => text.push_str("   "), // Align ASCII column of the last line
+            }
+        }
+        text.push_str(" |");
+        for b in line {
+            let c = if b.is_ascii_graphic() || *b == b' ' {
+                *b as char
+            } else {
+                '.'
+            };
+            text.push(c);
+        }
+        text.push_str("|\n");
+    }
+    text
+}
+
+// Position of the first hex digit of the byte at the offset
+pub fn pos_of_offset(offset: usize) -> (usize, usize) {
+    let i = offset % BYTES_PER_LINE;
+    let x = HEX_START + i * 3 + i / 8;
+    (x, offset / BYTES_PER_LINE)
+}
+
+// Offset of the byte at the position. Position outside hex bytes column is mapped to the nearest
+// byte in the line
+pub fn offset_at_pos((x, y): (usize, usize), len: usize) -> usize {
+    let x = x.saturating_sub(HEX_START);
+    let x = if x > 24 { x - 1 } else { x }; // Skip the extra space between 8th and 9th bytes
+    let i = x / 3;
+    let offset = y * BYTES_PER_LINE + i.min(BYTES_PER_LINE - 1);
+    offset.min(len.saturating_sub(1))
+}
+
+// Parse hex digits such as '7f 45 4c 46' or '7f454c46' into bytes
+pub fn parse_bytes(input: &str) -> Option<Vec<u8>> {
+    let digits = input
+        .chars()
+        .filter(|c| !c.is_ascii_whitespace())
+        .map(|c| c.to_digit(16).map(|d| d as u8))
+        .collect::<Option<Vec<_>>>()?;
+    if digits.is_empty() || digits.len() % 2 != 0 {
+        return None;
+    }
+    Some(digits.chunks(2).map(|d| d[0] << 4 | d[1]).collect())
+}
+
+// Parse offset in hex. '0x' prefix is optional
+pub fn parse_offset(input: &str) -> Option<usize> {
+    let input = input.trim();
+    let input = input.strip_prefix("0x").unwrap_or(input);
+    usize::from_str_radix(input, 16).ok()
+}
+
+// Find the bytes from the offset toward the direction. Search wraps around at the edge
+pub fn find(haystack: &[u8], needle: &[u8], offset: usize, forward: bool) -> Option<usize> {
+    let matches_at = |i: &usize| haystack[*i..].starts_with(needle);
+    let len = haystack.len();
+    if forward {
+        (offset..len).chain(0..offset.min(len)).find(matches_at)
+    } else {
+        (0..=offset.min(len.saturating_sub(1)))
+            .rev()
+            .chain((offset + 1..len).rev())
+            .find(matches_at)
+    }
+}
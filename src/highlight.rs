@@ -1,7 +1,8 @@
+use std::cmp;
 use std::iter;
 
 use crate::language::Language;
-use crate::row::Row;
+use crate::rope::Rope;
 use crate::term_color::Color;
 
 #[derive(Clone, Copy, PartialEq, Debug)]
@@ -19,6 +20,8 @@ pub enum Highlight {
     SpecialVar,
     Search,
     Match,
+    Selection,
+    ControlChar,
 }
 
 impl Highlight {
@@ -39,6 +42,8 @@ impl Highlight {
             SpecialVar => Cyan,
             Search => OrangeBg,
             Match => YellowBg,
+            Selection => GrayBg,
+            ControlChar => Cyan,
         }
     }
 }
@@ -668,7 +673,7 @@ impl<'a> Highlighter<'a> {
         fn iter_words<'a>(
             words: &'a [&'a str],
             hl: Highlight,
-        ) -> impl Iterator<Item = (&&'a str, Highlight)> {
+        ) -> impl Iterator<Item = (&'a &'a str, Highlight)> {
             words.iter().zip(iter::repeat(hl))
         }
 
@@ -682,12 +687,10 @@ impl<'a> Highlighter<'a> {
                 .chain(iter_words(self.syntax.special_vars, SpecialVar))
                 .find(|(k, _)| *k == ident);
 
-            let definition = keyword.or_else(|| {
-                if self.after_def_keyword {
-                    Some((ident, Highlight::Definition))
-                } else {
-                    None
-                }
+            let definition = keyword.or(if self.after_def_keyword {
+                Some((ident, Highlight::Definition))
+            } else {
+                None
             });
 
             if keyword.is_some() && self.syntax.definition_keywords.contains(ident) {
@@ -847,7 +850,7 @@ impl<'a> Highlighter<'a> {
                     // while statement always consume one character at top. Eat input chars considering that.
                     iter.nth(len.saturating_sub(2));
                 }
-                ParseStep::Ahead(len) if len == 1 => { /* Go next */ }
+                ParseStep::Ahead(1) => { /* Go next */ }
                 ParseStep::Ahead(_) => unreachable!(),
                 ParseStep::Break => break,
             }
@@ -868,8 +871,12 @@ impl RegionHighlight {
             false
         } else if sy < y && y < ey {
             true
-        } else {
+        } else if sy == ey {
             sx <= x && x < ex // Exclusive
+        } else if y == sy {
+            sx <= x
+        } else {
+            x < ex // Exclusive
         }
     }
 }
@@ -880,7 +887,11 @@ pub struct Highlighting {
     pub lines: Vec<Vec<Highlight>>, // TODO: One item per one character
     previous_bottom_of_screen: usize,
     matched: Vec<RegionHighlight>,
+    selection: Option<RegionHighlight>,
     syntax: &'static SyntaxHighlight,
+    // True after some control character was highlighted. On 'plain' syntax, lines need to be reset
+    // to normal color since highlighting is skipped
+    has_control_char: bool,
 }
 
 impl Default for Highlighting {
@@ -890,26 +901,24 @@ impl Default for Highlighting {
             lines: vec![],
             previous_bottom_of_screen: 0,
             matched: vec![],
+            selection: None,
             syntax: &PLAIN_SYNTAX,
+            has_control_char: false,
         }
     }
 }
 
 impl Highlighting {
-    pub fn new(lang: Language, rows: &[Row]) -> Highlighting {
+    // Lines are highlighted lazily until the bottom of screen by update()
+    pub fn new(lang: Language) -> Highlighting {
         Highlighting {
             needs_update: true,
-            lines: rows
-                .iter()
-                .map(|r| {
-                    iter::repeat(Highlight::Normal)
-                        .take(r.render_text().chars().count()) // TODO: One item per one character
-                        .collect()
-                })
-                .collect(),
+            lines: vec![],
             previous_bottom_of_screen: 0,
             matched: vec![],
+            selection: None,
             syntax: SyntaxHighlight::for_lang(lang),
+            has_control_char: false,
         }
     }
 
@@ -921,11 +930,14 @@ impl Highlighting {
         self.needs_update = true;
     }
 
-    fn highlight_match(&mut self, overwrite: Option<Highlight>) {
-        for region in self.matched.iter() {
-            let highlight = overwrite.unwrap_or(region.hl);
-            for y in region.start.1..=region.end.1 {
-                for (x, hl) in self.lines[y].iter_mut().enumerate() {
+    fn highlight_region(
+        lines: &mut [Vec<Highlight>],
+        region: &RegionHighlight,
+        highlight: Highlight,
+    ) {
+        for y in region.start.1..=region.end.1 {
+            if let Some(line) = lines.get_mut(y) {
+                for (x, hl) in line.iter_mut().enumerate() {
                     if region.contains((x, y)) {
                         *hl = highlight;
                     }
@@ -934,19 +946,38 @@ impl Highlighting {
         }
     }
 
-    pub fn update(&mut self, rows: &[Row], bottom_of_screen: usize) {
+    fn highlight_match(&mut self, overwrite: Option<Highlight>) {
+        for region in self.matched.iter() {
+            let highlight = overwrite.unwrap_or(region.hl);
+            Self::highlight_region(&mut self.lines, region, highlight);
+        }
+    }
+
+    pub fn update(&mut self, rows: &Rope, bottom_of_screen: usize) {
         if !self.needs_update && bottom_of_screen <= self.previous_bottom_of_screen {
             return;
         }
 
         let mut highlighter = Highlighter::new(self.syntax);
 
-        self.lines.resize_with(rows.len(), Default::default);
+        // Lines below the screen are not kept not to allocate memory for each line of large file
+        self.lines
+            .resize_with(cmp::min(rows.len(), bottom_of_screen), Default::default);
+        let plain = self.syntax.lang == Language::Plain;
         for (y, row) in rows.iter().enumerate().take(bottom_of_screen) {
-            let row = row.render_text();
-            self.lines[y].resize(row.chars().count(), Highlight::Normal); // TODO: One item per one character
+            let render = row.render_text();
+            let line = &mut self.lines[y];
+            line.resize(render.chars().count(), Highlight::Normal); // TODO: One item per one character
+            if plain && self.has_control_char {
+                line.fill(Highlight::Normal);
+            }
 
-            highlighter.highlight_line(&mut self.lines[y], row);
+            highlighter.highlight_line(line, &render);
+
+            for range in row.control_char_ranges() {
+                line[range].fill(Highlight::ControlChar);
+                self.has_control_char = true;
+            }
         }
 
         // Overwrite matched region
@@ -955,6 +986,11 @@ impl Highlighting {
         // Give self.matched to Highlighter::new() and it checks each cell should be highlighted as match
         self.highlight_match(None);
 
+        // Selection is put on top of all other highlights
+        if let Some(region) = &self.selection {
+            Self::highlight_region(&mut self.lines, region, region.hl);
+        }
+
         self.needs_update = false;
         self.previous_bottom_of_screen = bottom_of_screen;
     }
@@ -977,4 +1013,30 @@ impl Highlighting {
         }
         dirty_start
     }
+
+    // Returns the first line which needs to be redrawn when the selection was changed
+    pub fn set_selection(&mut self, selection: Option<RegionHighlight>) -> Option<usize> {
+        let range = |r: &Option<RegionHighlight>| r.as_ref().map(|r| (r.start, r.end));
+        if range(&self.selection) == range(&selection) {
+            return None;
+        }
+
+        let dirty_start = self
+            .selection
+            .iter()
+            .chain(selection.iter())
+            .map(|r| r.start.1)
+            .min();
+
+        if let Some(prev) = &self.selection {
+            if self.syntax.lang == Language::Plain {
+                // Back to normal color since highlighting is skipped on 'plain' syntax
+                Self::highlight_region(&mut self.lines, prev, Highlight::Normal);
+            }
+        }
+
+        self.selection = selection;
+        self.needs_update = true;
+        dirty_start
+    }
 }
@@ -1,5 +1,6 @@
 use crate::edit_diff::{EditDiff, UndoRedo};
-use crate::row::Row;
+use crate::rope::Rope;
+use crate::text_buffer::LineEnding;
 use std::cmp;
 use std::collections::VecDeque;
 use std::mem;
@@ -46,33 +47,42 @@ impl History {
     fn apply_diffs<'a, I: Iterator<Item = &'a EditDiff>>(
         diffs: I,
         which: UndoRedo,
-        rows: &mut Vec<Row>,
+        rows: &mut Rope,
+        eol: &mut LineEnding,
     ) -> (usize, usize, usize) {
-        diffs.fold((0, 0, usize::max_value()), |(_, _, dirty_start), diff| {
-            let (x, y) = diff.apply(rows, which);
+        diffs.fold((0, 0, usize::MAX), |(_, _, dirty_start), diff| {
+            let (x, y) = diff.apply(rows, eol, which);
             (x, y, cmp::min(dirty_start, y))
         })
     }
 
-    pub fn undo(&mut self, rows: &mut Vec<Row>) -> Option<(usize, usize, usize, bool)> {
+    pub fn undo(
+        &mut self,
+        rows: &mut Rope,
+        eol: &mut LineEnding,
+    ) -> Option<(usize, usize, usize, bool)> {
         let edited = self.finish_ongoing_edit();
         if self.index == 0 {
             return None;
         }
         self.index -= 1;
         let i = self.entries[self.index].iter().rev();
-        let (x, y, dirty_start) = Self::apply_diffs(i, UndoRedo::Undo, rows);
+        let (x, y, dirty_start) = Self::apply_diffs(i, UndoRedo::Undo, rows, eol);
         Some((x, y, dirty_start, edited))
     }
 
-    pub fn redo(&mut self, rows: &mut Vec<Row>) -> Option<(usize, usize, usize, bool)> {
+    pub fn redo(
+        &mut self,
+        rows: &mut Rope,
+        eol: &mut LineEnding,
+    ) -> Option<(usize, usize, usize, bool)> {
         let edited = self.finish_ongoing_edit();
         if self.index == self.entries.len() {
             return None;
         }
         self.index += 1;
         let i = self.entries[self.index - 1].iter();
-        let (x, y, dirty_start) = Self::apply_diffs(i, UndoRedo::Redo, rows);
+        let (x, y, dirty_start) = Self::apply_diffs(i, UndoRedo::Redo, rows, eol);
         Some((x, y, dirty_start, edited))
     }
 }
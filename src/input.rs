@@ -1,9 +1,11 @@
 use crate::error::{Error, Result};
 use std::fmt;
+use std::fs::File;
 use std::io::{self, Read};
 use std::ops::{Deref, DerefMut};
 use std::os::unix::io::AsRawFd;
 use std::str;
+use std::time::Duration;
 
 pub struct StdinRawMode {
     stdin: io::Stdin,
@@ -40,8 +42,22 @@ impl StdinRawMode {
         Ok(StdinRawMode { stdin, orig })
     }
 
+    // Replace stdin with the controlling terminal. This is necessary to read key inputs after
+    // reading text piped to stdin (e.g. `git log | kiro -`)
+    pub fn reopen_tty() -> Result<()> {
+        let tty = File::open("/dev/tty")?;
+        if unsafe { libc::dup2(tty.as_raw_fd(), libc::STDIN_FILENO) } == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(())
+    }
+
     pub fn input_keys(self) -> InputSequences {
-        InputSequences { stdin: self }
+        InputSequences {
+            stdin: self,
+            idle_timeout: None,
+            idle_notified: false,
+        }
     }
 }
 
@@ -81,6 +97,9 @@ pub enum KeySeq {
     EndKey,
     DeleteKey,
     Cursor(usize, usize), // Pseudo key (x, y)
+    Paste(String),        // Text pasted with bracketed paste mode
+    FocusIn,              // Terminal window gets focus
+    Idle,                 // Pseudo key sent when no key is input for a while
 }
 
 impl fmt::Display for KeySeq {
@@ -102,6 +121,9 @@ impl fmt::Display for KeySeq {
             EndKey => write!(f, "END"),
             DeleteKey => write!(f, "DELETE"),
             Cursor(r, c) => write!(f, "CURSOR({},{})", r, c),
+            Paste(_) => write!(f, "PASTE"),
+            FocusIn => write!(f, "FOCUSIN"),
+            Idle => write!(f, "IDLE"),
         }
     }
 }
@@ -153,9 +175,32 @@ impl fmt::Display for InputSeq {
 
 pub struct InputSequences {
     stdin: StdinRawMode,
+    idle_timeout: Option<Duration>,
+    idle_notified: bool, // Idle is sent only once until next key input
 }
 
 impl InputSequences {
+    // Send KeySeq::Idle when no key is input within the timeout
+    pub fn idle_timeout(mut self, timeout: Duration) -> Self {
+        self.idle_timeout = Some(timeout);
+        self
+    }
+
+    // Wait for input with poll(2) since blocking read(2) with VMIN=1 cannot time out. Returns
+    // false when timed out
+    fn wait_input(&self, timeout: Duration) -> Result<bool> {
+        let mut fds = libc::pollfd {
+            fd: self.stdin.as_raw_fd(),
+            events: libc::POLLIN,
+            revents: 0,
+        };
+        let millis = timeout.as_millis().min(libc::c_int::MAX as u128) as libc::c_int;
+        if unsafe { libc::poll(&mut fds, 1, millis) } == -1 {
+            return Err(io::Error::last_os_error().into());
+        }
+        Ok(fds.revents != 0)
+    }
+
     fn read_byte(&mut self) -> Result<Option<u8>> {
         let mut one_byte: [u8; 1] = [0];
         Ok(if self.stdin.read(&mut one_byte)? == 0 {
@@ -192,7 +237,8 @@ impl InputSequences {
                 match b {
                     // Control command chars from http://ascii-table.com/ansi-escape-sequences-vt-100.php
                     b'A' | b'B' | b'C' | b'D' | b'F' | b'H' | b'K' | b'J' | b'R' | b'c' | b'f'
-                    | b'g' | b'h' | b'l' | b'm' | b'n' | b'q' | b't' | b'y' | b'~' => break b,
+                    | b'g' | b'h' | b'l' | b'm' | b'n' | b'q' | b't' | b'y' | b'~' | b'I'
+                    | b'O' => break b,
                     _ => buf.push(b),
                 }
             } else {
@@ -232,6 +278,7 @@ impl InputSequences {
             b'~' => {
                 // e.g. \x1b[5~
                 match args.next() {
+                    Some(b"200") => self.read_bracketed_paste(),
                     Some(b"5") => Ok(InputSeq::new(PageUpKey)),
                     Some(b"6") => Ok(InputSeq::new(PageDownKey)),
                     Some(b"1") | Some(b"7") => Ok(InputSeq::new(HomeKey)),
@@ -251,10 +298,34 @@ impl InputSequences {
                 let alt = false;
                 Ok(InputSeq { key, ctrl, alt })
             }
+            // Focus events \x1b[I and \x1b[O. Focus out is not used
+            // https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h2-FocusIn_FocusOut
+            b'I' => Ok(InputSeq::new(FocusIn)),
+            b'O' => Ok(InputSeq::new(Unidentified)),
             _ => unreachable!(),
         }
     }
 
+    // Read pasted text until end of bracketed paste \x1b[201~. Pasted text is surrounded by
+    // \x1b[200~ and \x1b[201~ while bracketed paste mode is enabled.
+    // https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h2-Bracketed-Paste-Mode
+    fn read_bracketed_paste(&mut self) -> Result<InputSeq> {
+        const END: &[u8] = b"\x1b[201~";
+        let mut buf = vec![];
+        while let Some(b) = self.read_byte()? {
+            buf.push(b);
+            if buf.ends_with(END) {
+                buf.truncate(buf.len() - END.len());
+                break;
+            }
+        }
+        // Terminal sends \r as newline in pasted text
+        let text = String::from_utf8_lossy(&buf)
+            .replace("\r\n", "\n")
+            .replace('\r', "\n");
+        Ok(InputSeq::new(KeySeq::Paste(text)))
+    }
+
     fn decode_utf8(&mut self, b: u8) -> Result<InputSeq> {
         // TODO: Use arrayvec crate
         let mut buf = [0; 4];
@@ -321,6 +392,23 @@ impl Iterator for InputSequences {
     // Read next byte from stdin, if nothing was read, it returns InputSeq::Unidentified.
     // This method never returns None so for loop never ends.
     fn next(&mut self) -> Option<Self::Item> {
+        if let Some(timeout) = self.idle_timeout {
+            if !self.idle_notified {
+                match self.wait_input(timeout) {
+                    Ok(true) => {}
+                    Ok(false) => {
+                        self.idle_notified = true;
+                        return Some(Ok(InputSeq::new(KeySeq::Idle)));
+                    }
+                    // Interrupted by signal such as SIGWINCH. Return to handle the signal
+                    Err(Error::IoError(err)) if err.kind() == io::ErrorKind::Interrupted => {
+                        return Some(Ok(InputSeq::new(KeySeq::Unidentified)));
+                    }
+                    Err(err) => return Some(Err(err)),
+                }
+            }
+        }
+        self.idle_notified = false;
         Some(self.read_seq())
     }
 }
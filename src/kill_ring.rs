@@ -0,0 +1,57 @@
+use std::collections::VecDeque;
+
+const MAX_ENTRIES: usize = 60;
+
+// Killed texts shared by all text buffers. Texts are yanked from the ring as Emacs does
+#[derive(Default)]
+pub struct KillRing {
+    // The latest kill is at front
+    entries: VecDeque<String>,
+    // Index of entry yanked last. This is moved by yank-pop
+    yank_idx: usize,
+}
+
+impl KillRing {
+    pub fn push(&mut self, text: String) {
+        if text.is_empty() {
+            return;
+        }
+        if self.entries.len() == MAX_ENTRIES {
+            self.entries.pop_back();
+        }
+        self.entries.push_front(text);
+        self.yank_idx = 0;
+    }
+
+    // Concat killed text to the latest entry. This is used for consecutive kills
+    pub fn append(&mut self, text: &str) {
+        match self.entries.front_mut() {
+            Some(head) => head.push_str(text),
+            None => self.push(text.to_string()),
+        }
+        self.yank_idx = 0;
+    }
+
+    pub fn prepend(&mut self, text: &str) {
+        match self.entries.front_mut() {
+            Some(head) => head.insert_str(0, text),
+            None => self.push(text.to_string()),
+        }
+        self.yank_idx = 0;
+    }
+
+    pub fn head(&mut self) -> Option<&str> {
+        self.yank_idx = 0;
+        self.entries.front().map(String::as_str)
+    }
+
+    // Move to the previous entry of the entry yanked last. It cycles to the latest entry after the
+    // oldest one
+    pub fn rotate(&mut self) -> Option<&str> {
+        if self.entries.is_empty() {
+            return None;
+        }
+        self.yank_idx = (self.yank_idx + 1) % self.entries.len();
+        self.entries.get(self.yank_idx).map(String::as_str)
+    }
+}
@@ -0,0 +1,110 @@
+// Direction to split a window into two
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum SplitDir {
+    Horizontal, // One window above another
+    Vertical,   // Windows side by side
+}
+
+// Area of window on terminal. All values are 0-based and in cells
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub struct Rect {
+    pub top: usize,
+    pub left: usize,
+    pub width: usize,
+    pub height: usize,
+}
+
+// Tree of split windows. Each leaf has an ID of window
+#[derive(Debug)]
+pub enum Layout {
+    Window(usize),
+    Split(SplitDir, Box<Layout>, Box<Layout>),
+}
+
+impl Layout {
+    // Split the window into two. The new window is put at bottom or right of the window
+    pub fn split(&mut self, id: usize, new_id: usize, dir: SplitDir) -> bool {
+        match self {
+            Layout::Window(i) if *i == id => {
+                let first = Box::new(Layout::Window(id));
+                let second = Box::new(Layout::Window(new_id));
+                *self = Layout::Split(dir, first, second);
+                true
+            }
+            Layout::Window(_) => false,
+            Layout::Split(_, first, second) => {
+                first.split(id, new_id, dir) || second.split(id, new_id, dir)
+            }
+        }
+    }
+
+    // Remove the window and give its area to the sibling. IDs of windows after the removed one
+    // are shifted to keep them contiguous. The last window cannot be removed.
+    pub fn remove(&mut self, id: usize) -> bool {
+        let removed = self.remove_leaf(id);
+        if removed {
+            self.shift_ids(id);
+        }
+        removed
+    }
+
+    fn remove_leaf(&mut self, id: usize) -> bool {
+        let Layout::Split(_, first, second) = self else {
+            return false;
+        };
+        let sibling = match (&**first, &**second) {
+            (Layout::Window(i), _) if *i == id => second,
+            (_, Layout::Window(i)) if *i == id => first,
+            _ => return first.remove_leaf(id) || second.remove_leaf(id),
+        };
+        let sibling = std::mem::replace(&mut **sibling, Layout::Window(id));
+        *self = sibling;
+        true
+    }
+
+    fn shift_ids(&mut self, removed: usize) {
+        match self {
+            Layout::Window(i) if *i > removed => *i -= 1,
+            Layout::Window(_) => {}
+            Layout::Split(_, first, second) => {
+                first.shift_ids(removed);
+                second.shift_ids(removed);
+            }
+        }
+    }
+
+    // Calculate areas of windows in the area. Windows are ordered from top-left to bottom-right.
+    pub fn rects(&self, area: Rect, out: &mut Vec<(usize, Rect)>) {
+        match self {
+            Layout::Window(id) => out.push((*id, area)),
+            Layout::Split(SplitDir::Horizontal, first, second) => {
+                let height = area.height / 2;
+                first.rects(Rect { height, ..area }, out);
+                let top = area.top + height;
+                let height = area.height - height;
+                second.rects(
+                    Rect {
+                        top,
+                        height,
+                        ..area
+                    },
+                    out,
+                );
+            }
+            Layout::Split(SplitDir::Vertical, first, second) => {
+                let width = area.width / 2;
+                first.rects(Rect { width, ..area }, out);
+                let left = area.left + width;
+                let width = area.width - width;
+                second.rects(
+                    Rect {
+                        left,
+                        width,
+                        ..area
+                    },
+                    out,
+                );
+            }
+        }
+    }
+}
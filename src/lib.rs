@@ -8,24 +8,36 @@
 #![allow(clippy::useless_let_if_seq)]
 #![allow(clippy::cognitive_complexity)]
 
+mod clipboard;
 mod edit_diff;
 mod editor;
+mod encoding;
 mod error;
+mod file_arg;
+mod hex_dump;
 mod highlight;
 mod history;
 mod input;
+mod kill_ring;
 mod language;
+mod layout;
 mod prompt;
+mod rope;
 mod row;
+mod save;
 mod screen;
 mod signal;
 mod status_bar;
+mod swap;
 mod term_color;
 mod text_buffer;
 
+pub use clipboard::Clipboard;
 pub use editor::Editor;
 pub use error::{Error, Result};
+pub use file_arg::FileArg;
 pub use input::{InputSeq, KeySeq, StdinRawMode};
 pub use language::Language;
-pub use screen::{Screen, HELP, VERSION};
+pub use save::{Backup, BackupMode};
+pub use screen::{LineNumbers, Screen, HELP, VERSION};
 pub use text_buffer::{Lines, TextBuffer};
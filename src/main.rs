@@ -2,10 +2,18 @@
 //   Build Your Own Text Editor: https://viewsourcecode.org/snaptoken/kilo/index.html
 //   VT100 User Guide: https://vt100.net/docs/vt100-ug/chapter3.html
 use getopts::Options;
-use kiro_editor::{self as kiro, Editor, StdinRawMode, HELP, VERSION};
+use kiro_editor::{
+    self as kiro, Backup, BackupMode, Clipboard, Editor, FileArg, LineNumbers, StdinRawMode,
+    TextBuffer, HELP, VERSION,
+};
 use std::env;
-use std::io;
+use std::io::{self, IsTerminal};
+use std::path::PathBuf;
 use std::process::exit;
+use std::time::Duration;
+
+// Swap files are written after this idle time when autosave is disabled
+const DEFAULT_IDLE_SECS: u64 = 4;
 
 #[global_allocator]
 static ALLOC: jemallocator::Jemalloc = jemallocator::Jemalloc;
@@ -20,7 +28,8 @@ start to write a new text.
 Help can show up with key mapping Ctrl-?.
 
 Usage:
-    {prog} [options] [FILES...]
+    {prog} [options] [[+LINE] FILE[:LINE[:COL]]...]
+    {prog} [options] -    (Read text from stdin)
 
 Mappings:
     {maps}",
@@ -30,10 +39,33 @@ Mappings:
     println!("{}", opts.usage(&description));
 }
 
-fn edit(files: Vec<String>) -> kiro::Result<()> {
-    // TODO: Read input from stdin before start
-    let input = StdinRawMode::new()?.input_keys();
-    Editor::open(input, io::stdout(), None, &files)?.edit()
+fn edit(
+    mut files: Vec<FileArg>,
+    clipboard: Clipboard,
+    line_numbers: LineNumbers,
+    soft_wrap: bool,
+    autosave: Option<u64>,
+    backup: Backup,
+) -> kiro::Result<()> {
+    // Read text piped to stdin when '-' is given or stdin is not a terminal
+    let piped = if files.iter().any(|f| f.path == "-") || !io::stdin().is_terminal() {
+        files.retain(|f| f.path != "-");
+        let buf = TextBuffer::from_reader(io::stdin())?;
+        StdinRawMode::reopen_tty()?; // Read key inputs from terminal instead
+        Some(buf)
+    } else {
+        None
+    };
+
+    let idle = Duration::from_secs(autosave.unwrap_or(DEFAULT_IDLE_SECS));
+    let input = StdinRawMode::new()?.input_keys().idle_timeout(idle);
+    let mut editor = Editor::open_files(input, io::stdout(), None, &files, piped)?;
+    editor.set_clipboard(clipboard);
+    editor.set_line_numbers(line_numbers);
+    editor.set_soft_wrap(soft_wrap);
+    editor.set_autosave(autosave.is_some());
+    editor.set_backup(backup);
+    editor.edit()
 }
 
 fn main() {
@@ -43,6 +75,48 @@ fn main() {
     let mut opts = Options::new();
     opts.optflag("v", "version", "Print version");
     opts.optflag("h", "help", "Print this help");
+    opts.optopt(
+        "",
+        "copy-command",
+        "Command to copy text to clipboard. Text is given via stdin. OSC 52 escape sequence is used by default",
+        "CMD",
+    );
+    opts.optopt(
+        "",
+        "paste-command",
+        "Command to paste text from clipboard. Text is read from its stdout",
+        "CMD",
+    );
+
+    opts.optopt(
+        "",
+        "line-numbers",
+        "Show line numbers at left of text. MODE is 'absolute' or 'relative'",
+        "MODE",
+    );
+    opts.optflag(
+        "",
+        "soft-wrap",
+        "Wrap long lines at the right edge of screen instead of scrolling horizontally",
+    );
+    opts.optopt(
+        "",
+        "autosave",
+        "Save modified files after SECS seconds of no input and on switching buffers",
+        "SECS",
+    );
+    opts.optopt(
+        "",
+        "backup",
+        "Back up original file on first save. MODE is 'off' (default), 'single' (file~) or 'numbered' (file.~N~)",
+        "MODE",
+    );
+    opts.optopt(
+        "",
+        "backup-dir",
+        "Directory to put backup files in. Backup is 'single' by default with this option",
+        "DIR",
+    );
 
     let matches = match opts.parse(argv) {
         Ok(m) => m,
@@ -62,7 +136,67 @@ fn main() {
         return;
     }
 
-    if let Err(err) = edit(matches.free) {
+    let clipboard = match matches.opt_str("copy-command") {
+        Some(copy) => Clipboard::Command {
+            copy,
+            paste: matches.opt_str("paste-command"),
+        },
+        None if matches.opt_present("paste-command") => {
+            eprintln!("Error: --paste-command requires --copy-command");
+            exit(1);
+        }
+        None => Clipboard::Osc52,
+    };
+
+    let line_numbers = match matches.opt_str("line-numbers").as_deref() {
+        None => LineNumbers::Off,
+        Some("absolute") => LineNumbers::Absolute,
+        Some("relative") => LineNumbers::Relative,
+        Some(mode) => {
+            eprintln!(
+                "Error: Unknown mode for --line-numbers: {:?}. It must be 'absolute' or 'relative'",
+                mode,
+            );
+            exit(1);
+        }
+    };
+
+    let soft_wrap = matches.opt_present("soft-wrap");
+
+    let autosave = match matches.opt_str("autosave").map(|s| s.parse::<u64>()) {
+        None => None,
+        Some(Ok(secs)) if secs > 0 => Some(secs),
+        Some(_) => {
+            eprintln!("Error: --autosave must be a positive number of seconds");
+            exit(1);
+        }
+    };
+
+    let dir = matches.opt_str("backup-dir").map(PathBuf::from);
+    let mode = match matches.opt_str("backup").as_deref() {
+        None if dir.is_some() => BackupMode::Single,
+        None | Some("off") => BackupMode::Off,
+        Some("single") => BackupMode::Single,
+        Some("numbered") => BackupMode::Numbered,
+        Some(mode) => {
+            eprintln!(
+                "Error: Unknown mode for --backup: {:?}. It must be 'off', 'single' or 'numbered'",
+                mode,
+            );
+            exit(1);
+        }
+    };
+    let backup = Backup { mode, dir };
+
+    let files = match FileArg::parse_args(&matches.free) {
+        Ok(files) => files,
+        Err(msg) => {
+            eprintln!("Error: {}", msg);
+            exit(1);
+        }
+    };
+
+    if let Err(err) = edit(files, clipboard, line_numbers, soft_wrap, autosave, backup) {
         eprintln!("Error: {}", err);
         exit(1);
     }
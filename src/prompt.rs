@@ -1,12 +1,15 @@
 use crate::error::Result;
+use crate::hex_dump;
 use crate::highlight::{Highlight, Highlighting, RegionHighlight};
 use crate::input::{InputSeq, KeySeq};
-use crate::row::Row;
+use crate::rope::Rope;
 use crate::screen::Screen;
 use crate::status_bar::StatusBar;
 use crate::text_buffer::TextBuffer;
+use regex::{Regex, RegexBuilder};
 use std::cmp::{self, Ordering};
 use std::io::Write;
+use std::rc::Rc;
 
 #[derive(PartialEq)]
 pub enum PromptResult {
@@ -50,13 +53,205 @@ enum FindDir {
     Forward,
 }
 
+#[derive(Clone, Copy, PartialEq, Debug)]
+enum CaseMode {
+    Sensitive,
+    Insensitive,
+    Smart, // Insensitive unless query contains uppercase letter
+}
+
+impl CaseMode {
+    fn next(self) -> Self {
+        match self {
+            CaseMode::Sensitive => CaseMode::Insensitive,
+            CaseMode::Insensitive => CaseMode::Smart,
+            CaseMode::Smart => CaseMode::Sensitive,
+        }
+    }
+
+    fn ignore_case(self, query: &str) -> bool {
+        match self {
+            CaseMode::Sensitive => false,
+            CaseMode::Insensitive => true,
+            CaseMode::Smart => !query.chars().any(char::is_uppercase),
+        }
+    }
+}
+
+// Direction to move to next match by the key. None means the key edits query
+fn find_dir_of(seq: &InputSeq) -> Option<FindDir> {
+    use KeySeq::*;
+    match (&seq.key, seq.ctrl) {
+        (RightKey, ..) | (DownKey, ..) | (Key(b'f'), true) | (Key(b'n'), true) => {
+            Some(FindDir::Forward)
+        }
+        (LeftKey, ..) | (UpKey, ..) | (Key(b'b'), true) | (Key(b'p'), true) => Some(FindDir::Back),
+        _ => None,
+    }
+}
+
+// Query matcher with regex and case modes. It is shared by text search and query replace
+pub struct Matcher {
+    use_regex: bool,
+    case: CaseMode,
+    regex: Option<Regex>, // Compiled query when searching with regex or ignoring case
+}
+
+impl Matcher {
+    fn new() -> Self {
+        Self {
+            use_regex: false,
+            case: CaseMode::Sensitive,
+            regex: None,
+        }
+    }
+
+    // Returns bool which represents whether search mode was toggled by M-R or M-C
+    fn toggle(&mut self, seq: &InputSeq) -> bool {
+        match seq.key {
+            KeySeq::Key(b'r') if seq.alt => self.use_regex = !self.use_regex,
+            KeySeq::Key(b'c') if seq.alt => self.case = self.case.next(),
+            _ => return false,
+        }
+        true
+    }
+
+    pub fn label(&self) -> String {
+        let mut label = String::new();
+        if self.use_regex {
+            label.push_str("[Regex] ");
+        }
+        match self.case {
+            CaseMode::Sensitive => {}
+            CaseMode::Insensitive => label.push_str("[Ignore case] "),
+            CaseMode::Smart => label.push_str("[Smart case] "),
+        }
+        label
+    }
+
+    // Returns error message when the query is not a valid regular expression
+    fn compile(&mut self, input: &str) -> std::result::Result<(), String> {
+        let ignore_case = self.case.ignore_case(input);
+        if !self.use_regex && !ignore_case {
+            self.regex = None; // Plain text search is faster
+            return Ok(());
+        }
+
+        // Text is searched with regex even if regex mode is off since regex crate folds case
+        // correctly for non-ASCII characters and the match offsets point to the original text
+        let pattern = if self.use_regex {
+            input.to_string()
+        } else {
+            regex::escape(input)
+        };
+
+        // Enable multi_line so that ^ and $ match at start and end of each line
+        match RegexBuilder::new(&pattern)
+            .multi_line(true)
+            .case_insensitive(ignore_case)
+            .build()
+        {
+            Ok(re) => {
+                self.regex = Some(re);
+                Ok(())
+            }
+            Err(err) => {
+                // Error message of regex crate consists of multiple lines. The last line describes
+                // the reason (e.g. "error: unclosed group")
+                let msg = err.to_string();
+                let reason = msg.lines().last().unwrap_or("");
+                let reason = reason.trim_start_matches("error: ");
+                self.regex = None;
+                Err(format!("Invalid regex: {}", reason))
+            }
+        }
+    }
+
+    // Returns byte offsets of matches which start in the range
+    fn matches_in(&self, text: &str, query: &str, start: usize, end: usize) -> Vec<(usize, usize)> {
+        if let Some(re) = &self.regex {
+            // Do not slice text since matching multiple lines may continue after the end
+            let mut found = vec![];
+            let mut pos = start;
+            while pos < end {
+                let m = match re.find_at(text, pos) {
+                    Some(m) if m.start() < end => m,
+                    _ => break,
+                };
+                found.push((m.start(), m.end()));
+                pos = if m.start() < m.end() {
+                    m.end()
+                } else {
+                    // Skip one character to avoid matching the same empty match forever
+                    m.end() + text[m.end()..].chars().next().map_or(1, char::len_utf8)
+                };
+            }
+            found
+        } else {
+            text[start..end]
+                .match_indices(query)
+                .map(|(idx, _)| (start + idx, start + idx + query.len()))
+                .collect()
+        }
+    }
+
+    // Returns start and end byte offsets of the match. Search wraps around at the end of text
+    fn find_at(&self, text: &str, query: &str, off: usize, dir: FindDir) -> Option<(usize, usize)> {
+        if let Some(re) = &self.regex {
+            if text.is_empty() {
+                return None; // Empty pattern like ^ matches to empty buffer which has no row
+            }
+            let found = match dir {
+                FindDir::Forward => re.find_at(text, off).or_else(|| re.find(text)),
+                FindDir::Back => re
+                    .find_iter(text)
+                    .take_while(|m| m.start() < off)
+                    .last()
+                    .or_else(|| re.find_iter(text).last()),
+            };
+            return found.map(|m| (m.start(), m.end()));
+        }
+
+        let found = match dir {
+            FindDir::Forward => text[off..]
+                .find(query)
+                .map(|idx| idx + off)
+                .or_else(|| text[..off].find(query)),
+            FindDir::Back => text[..off]
+                .rfind(query)
+                .or_else(|| text[off..].rfind(query).map(|idx| idx + off)),
+        };
+        found.map(|idx| (idx, idx + query.len()))
+    }
+
+    // Returns byte offsets of the first non-empty match in the line after the offset. Empty match
+    // such as ^ is skipped since replacing it never moves forward
+    pub fn find_in_line(&self, line: &str, query: &str, off: usize) -> Option<(usize, usize)> {
+        if let Some(re) = &self.regex {
+            let mut off = off;
+            while off <= line.len() {
+                let m = re.find_at(line, off)?;
+                if m.start() < m.end() {
+                    return Some((m.start(), m.end()));
+                }
+                off = m.end() + line[m.end()..].chars().next().map_or(1, char::len_utf8);
+            }
+            return None;
+        }
+        line[off..]
+            .find(query)
+            .map(|idx| (off + idx, off + idx + query.len()))
+    }
+}
+
 pub struct TextSearch {
     saved: ((usize, usize), (usize, usize)),
     dir: FindDir,
     matched: bool,
-    text: Box<str>,
+    text: Rc<String>,
     line_starts: Box<[usize]>,
     current_offset: usize,
+    matcher: Matcher,
 }
 
 impl TextSearch {
@@ -70,19 +265,28 @@ impl TextSearch {
         }
     }
 
-    fn handle_seq(&mut self, seq: InputSeq) {
-        use KeySeq::*;
-        match (seq.key, seq.ctrl) {
-            (RightKey, ..) | (DownKey, ..) | (Key(b'f'), true) | (Key(b'n'), true) => {
-                self.dir = FindDir::Forward;
-            }
-            (LeftKey, ..) | (UpKey, ..) | (Key(b'b'), true) | (Key(b'p'), true) => {
-                self.dir = FindDir::Back;
-            }
-            _ => {
-                self.matched = false; // Clear since new input might change input
-            }
+    fn restore<W: Write>(&self, prompt: &mut Prompt<'_, W>) {
+        let ((cx, cy), (rowoff, coloff)) = self.saved;
+        prompt.buf.set_cursor(cx, cy);
+        prompt.screen.rowoff = rowoff;
+        prompt.screen.coloff = coloff;
+        prompt.screen.set_dirty_start(prompt.screen.rowoff); // Redraw all lines
+    }
+
+    // Returns bool which represents whether search mode was toggled
+    fn handle_seq(&mut self, seq: InputSeq) -> bool {
+        if self.matcher.toggle(&seq) {
+            self.matched = false; // Search again at current position with new mode
+            return true;
+        }
+        if seq.alt {
+            return false;
         }
+        match find_dir_of(&seq) {
+            Some(dir) => self.dir = dir,
+            None => self.matched = false, // Clear since new input might change input
+        }
+        false
     }
 
     fn reject_match_to_current(&mut self) {
@@ -96,8 +300,7 @@ impl TextSearch {
                 .unwrap_or(0),
             FindDir::Back => self.text[..self.current_offset]
                 .char_indices()
-                .rev()
-                .next()
+                .next_back()
                 .map(|(idx, _)| idx)
                 .unwrap_or_else(|| self.text.len()),
         };
@@ -108,7 +311,7 @@ impl TextSearch {
         query: &str,
         current_match: RegionHighlight,
         screen: &Screen<W>,
-        rows: &[Row],
+        rows: &Rope,
     ) -> Vec<RegionHighlight> {
         // Match at current cursor position
         let mut matches = vec![];
@@ -124,17 +327,17 @@ impl TextSearch {
         };
 
         // Scan screen again to get all 'other' matches than current match
-        for offset in self.text[start_offset..end_offset]
-            .match_indices(query)
-            .map(|(idx, _)| start_offset + idx)
+        for (start, end) in self
+            .matcher
+            .matches_in(&self.text, query, start_offset, end_offset)
         {
-            if offset == self.current_offset {
+            if start == self.current_offset || start == end {
                 continue; // Exclude current match since it is already included in matches
             }
             matches.push(RegionHighlight {
                 hl: Highlight::Match,
-                start: self.offset_to_pos(offset, rows),
-                end: self.offset_to_pos(offset + query.len(), rows),
+                start: self.offset_to_pos(start, rows),
+                end: self.offset_to_pos(end, rows),
             });
         }
 
@@ -146,16 +349,17 @@ impl TextSearch {
     }
 
     fn search<W: Write>(&mut self, input: &str, prompt: &mut Prompt<'_, W>) {
-        if let Some(offset) = self.find_at(input, self.current_offset) {
-            self.current_offset = offset;
+        let end = if let Some((start, end)) = self.find_at(input, self.current_offset) {
+            self.current_offset = start;
+            end
         } else {
             return;
-        }
+        };
 
         let current_match = RegionHighlight {
             hl: Highlight::Search,
             start: self.offset_to_pos(self.current_offset, prompt.buf.rows()),
-            end: self.offset_to_pos(self.current_offset + input.len(), prompt.buf.rows()),
+            end: self.offset_to_pos(end, prompt.buf.rows()),
         };
         let (x, y) = current_match.start;
         prompt.buf.set_cursor(x, y);
@@ -195,44 +399,38 @@ impl TextSearch {
         bsearch_nearest_line(&self.line_starts, 0, self.line_starts.len(), byte_offset)
     }
 
-    fn offset_to_pos(&self, byte_offset: usize, rows: &[Row]) -> (usize, usize) {
+    fn offset_to_pos(&self, byte_offset: usize, rows: &Rope) -> (usize, usize) {
+        if byte_offset == self.text.len() {
+            // Regex match may end after the newline at the end of text
+            let y = rows.len() - 1;
+            return (rows[y].len(), y);
+        }
         let y = self.nearest_line(byte_offset);
         let y_offset = self.line_starts[y];
         let x_offset = byte_offset - y_offset;
         (rows[y].char_idx_of(x_offset), y)
     }
 
-    fn pos_to_offset(&self, pos: (usize, usize), rows: &[Row]) -> usize {
+    fn pos_to_offset(&self, pos: (usize, usize), rows: &Rope) -> usize {
         let y = pos.1;
         let x = rows[y].byte_idx_of(pos.0);
         self.line_starts[y] + x
     }
 
-    fn find_at(&self, query: &str, off: usize) -> Option<usize> {
-        match self.dir {
-            FindDir::Forward => self.text[off..]
-                .find(query)
-                .map(|idx| idx + off)
-                .or_else(|| self.text[..off].find(query)),
-            FindDir::Back => self.text[..off]
-                .rfind(query)
-                .or_else(|| self.text[off..].rfind(query).map(|idx| idx + off)),
-        }
+    fn find_at(&self, query: &str, off: usize) -> Option<(usize, usize)> {
+        self.matcher.find_at(&self.text, query, off, self.dir)
     }
 }
 
 impl Action for TextSearch {
     fn new<W: Write>(prompt: &mut Prompt<'_, W>) -> Self {
         let rows = prompt.buf.rows();
-        let cap = rows.iter().fold(0, |acc, row| acc + row.buffer().len() + 1);
-        let mut text = String::with_capacity(cap);
+        let text = prompt.buf.text();
 
         let mut pos = 0;
         let mut line_starts = Vec::with_capacity(rows.len());
         for row in rows {
             line_starts.push(pos);
-            text.push_str(row.buffer());
-            text.push('\n');
             pos += row.buffer().len() + 1;
         }
 
@@ -243,9 +441,10 @@ impl Action for TextSearch {
             ),
             dir: FindDir::Forward,
             matched: false,
-            text: text.into_boxed_str(),
+            text,
             line_starts: line_starts.into_boxed_slice(),
             current_offset: 0, // Set later
+            matcher: Matcher::new(),
         };
 
         let cursor = prompt.buf.cursor();
@@ -266,10 +465,19 @@ impl Action for TextSearch {
         seq: InputSeq,
     ) -> Result<bool> {
         self.cleanup_match_highlight(prompt);
-        self.handle_seq(seq);
+        let toggled = self.handle_seq(seq);
+        if toggled {
+            prompt.label = self.matcher.label();
+        }
+        prompt.error = None;
 
         if input.is_empty() {
-            return Ok(false);
+            return Ok(toggled);
+        }
+
+        if let Err(msg) = self.matcher.compile(input) {
+            prompt.error = Some(msg);
+            return Ok(true); // Invalid pattern is reported in prompt
         }
 
         if self.matched {
@@ -302,6 +510,134 @@ impl Action for TextSearch {
             }
         };
 
+        if result == Canceled {
+            self.restore(prompt);
+        }
+
+        Ok(result)
+    }
+}
+
+// Search bytes in hex view of binary file. Query is hex digits such as '7f 45 4c 46'
+pub struct HexSearch {
+    saved: ((usize, usize), (usize, usize)),
+    bytes: Rc<Vec<u8>>,
+    dir: FindDir,
+    matched: bool,
+    current_offset: usize,
+}
+
+impl HexSearch {
+    fn cleanup_match_highlight<W: Write>(&self, prompt: &mut Prompt<'_, W>) {
+        if !self.matched {
+            return;
+        }
+        if let Some(matched_line) = prompt.hl.clear_previous_match() {
+            prompt.hl.needs_update = true;
+            prompt.screen.set_dirty_start(matched_line);
+        }
+    }
+
+    fn search<W: Write>(&mut self, needle: &[u8], prompt: &mut Prompt<'_, W>) {
+        let forward = matches!(self.dir, FindDir::Forward);
+        let mut offset = self.current_offset;
+        if self.matched {
+            // Move to next match by rejecting current offset
+            offset = if forward {
+                offset + 1
+            } else {
+                offset.checked_sub(1).unwrap_or(self.bytes.len())
+            };
+        }
+
+        let found = match hex_dump::find(&self.bytes, needle, offset, forward) {
+            Some(found) => found,
+            None => return,
+        };
+        self.current_offset = found;
+
+        let start = hex_dump::pos_of_offset(found);
+        let (x, y) = hex_dump::pos_of_offset(found + needle.len() - 1);
+        let end = (x + 2, y); // Just after the hex digits of the last byte
+        prompt.buf.set_cursor(start.0, start.1);
+
+        // Scroll to put the match at center of screen as TextSearch does
+        prompt.screen.rowoff = start.1.saturating_sub(prompt.screen.rows() / 2);
+        prompt.screen.coloff = 0;
+
+        prompt.hl.set_matches(vec![RegionHighlight {
+            hl: Highlight::Search,
+            start,
+            end,
+        }]);
+        prompt.hl.needs_update = true;
+        prompt.screen.set_dirty_start(prompt.screen.rowoff);
+
+        self.matched = true;
+    }
+}
+
+impl Action for HexSearch {
+    fn new<W: Write>(prompt: &mut Prompt<'_, W>) -> Self {
+        let bytes = prompt.buf.binary().unwrap_or_default();
+        let current_offset = hex_dump::offset_at_pos(prompt.buf.cursor(), bytes.len());
+        Self {
+            saved: (
+                prompt.buf.cursor(),
+                (prompt.screen.rowoff, prompt.screen.coloff),
+            ),
+            bytes,
+            dir: FindDir::Forward,
+            matched: false,
+            current_offset,
+        }
+    }
+
+    fn on_seq<W: Write>(
+        &mut self,
+        prompt: &mut Prompt<'_, W>,
+        input: &str,
+        seq: InputSeq,
+    ) -> Result<bool> {
+        self.cleanup_match_highlight(prompt);
+        match find_dir_of(&seq) {
+            Some(dir) => self.dir = dir,
+            None => self.matched = false,
+        }
+        prompt.error = None;
+
+        if input.trim().is_empty() {
+            return Ok(false);
+        }
+
+        match hex_dump::parse_bytes(input) {
+            Some(needle) => self.search(&needle, prompt),
+            None => prompt.error = Some("Invalid hex bytes".to_string()),
+        }
+        Ok(true)
+    }
+
+    fn on_end<W: Write>(
+        self,
+        prompt: &mut Prompt<'_, W>,
+        result: PromptResult,
+    ) -> Result<PromptResult> {
+        self.cleanup_match_highlight(prompt);
+
+        use PromptResult::*;
+        let result = match &result {
+            Canceled => Canceled,
+            Input(i) if i.trim().is_empty() => Canceled,
+            Input(_) if self.matched => {
+                prompt.screen.set_info_message("Found");
+                result
+            }
+            Input(_) => {
+                prompt.screen.set_info_message("Not found");
+                result
+            }
+        };
+
         if result == Canceled {
             let ((cx, cy), (rowoff, coloff)) = self.saved;
             prompt.buf.set_cursor(cx, cy);
@@ -314,6 +650,132 @@ impl Action for TextSearch {
     }
 }
 
+pub struct GoToLine {
+    saved: ((usize, usize), (usize, usize)),
+    binary_len: Option<usize>, // Go to byte offset instead of line in hex view
+}
+
+impl GoToLine {
+    // Parse 'LINE', 'LINE:COL', '+N' or '-N'. Line and column are 1-based. Returns (x, y) of the
+    // target position clamped into the buffer. In hex view, input is byte offset in hex
+    fn parse_target(&self, input: &str, rows: &Rope) -> Option<(usize, usize)> {
+        if let Some(len) = self.binary_len {
+            let offset = hex_dump::parse_offset(input)?;
+            return Some(hex_dump::pos_of_offset(offset.min(len.saturating_sub(1))));
+        }
+
+        let input = input.trim();
+        let (line, col) = match input.split_once(':') {
+            Some((line, col)) => (line, Some(col.parse::<usize>().ok()?)),
+            None => (input, None),
+        };
+
+        let cy = (self.saved.0).1;
+        let y = if let Some(n) = line.strip_prefix('+') {
+            cy.saturating_add(n.parse().ok()?)
+        } else if let Some(n) = line.strip_prefix('-') {
+            cy.saturating_sub(n.parse().ok()?)
+        } else {
+            line.parse::<usize>().ok()?.saturating_sub(1)
+        };
+
+        if rows.is_empty() {
+            return Some((0, 0));
+        }
+        let y = cmp::min(y, rows.len() - 1);
+        let x = col.map(|c| c.saturating_sub(1)).unwrap_or(0);
+        let x = cmp::min(x, rows[y].len());
+        Some((x, y))
+    }
+
+    fn target_name(&self) -> &'static str {
+        if self.binary_len.is_some() {
+            "offset"
+        } else {
+            "line number"
+        }
+    }
+
+    fn restore<W: Write>(&self, prompt: &mut Prompt<'_, W>) {
+        let ((cx, cy), (rowoff, coloff)) = self.saved;
+        prompt.buf.set_cursor(cx, cy);
+        prompt.screen.rowoff = rowoff;
+        prompt.screen.coloff = coloff;
+        prompt.screen.set_dirty_start(prompt.screen.rowoff); // Redraw all lines
+    }
+
+    fn jump<W: Write>(&self, (x, y): (usize, usize), prompt: &mut Prompt<'_, W>) {
+        prompt.buf.set_cursor(x, y);
+        // Center the target line in screen
+        prompt.screen.rowoff = y.saturating_sub(prompt.screen.rows() / 2);
+        prompt.screen.coloff = 0;
+        prompt.screen.set_dirty_start(prompt.screen.rowoff);
+    }
+}
+
+impl Action for GoToLine {
+    fn new<W: Write>(prompt: &mut Prompt<'_, W>) -> Self {
+        Self {
+            saved: (
+                prompt.buf.cursor(),
+                (prompt.screen.rowoff, prompt.screen.coloff),
+            ),
+            binary_len: prompt.buf.binary().map(|b| b.len()),
+        }
+    }
+
+    fn on_seq<W: Write>(
+        &mut self,
+        prompt: &mut Prompt<'_, W>,
+        input: &str,
+        _seq: InputSeq,
+    ) -> Result<bool> {
+        prompt.error = None;
+        if input.is_empty() {
+            self.restore(prompt);
+            return Ok(true);
+        }
+        if let Some(pos) = self.parse_target(input, prompt.buf.rows()) {
+            self.jump(pos, prompt); // Preview the target line
+            Ok(true)
+        } else {
+            prompt.error = Some(format!("Invalid {}", self.target_name()));
+            Ok(true)
+        }
+    }
+
+    fn on_end<W: Write>(
+        self,
+        prompt: &mut Prompt<'_, W>,
+        result: PromptResult,
+    ) -> Result<PromptResult> {
+        let target = match &result {
+            PromptResult::Input(input) => self.parse_target(input, prompt.buf.rows()),
+            PromptResult::Canceled => None,
+        };
+
+        if let Some(pos) = target {
+            self.jump(pos, prompt);
+            return Ok(result);
+        }
+
+        if let PromptResult::Input(input) = &result {
+            prompt
+                .screen
+                .set_error_message(format!("Invalid {}: {:?}", self.target_name(), input));
+        }
+        self.restore(prompt);
+        Ok(PromptResult::Canceled)
+    }
+}
+
+enum ReplaceAnswer {
+    Yes,
+    No,
+    All,
+    Quit,
+}
+
 struct PromptTemplate<'a> {
     prefix: &'a str,
     suffix: &'a str,
@@ -330,17 +792,25 @@ impl<'a> PromptTemplate<'a> {
         }
     }
 
-    fn build(&self, input: &str) -> String {
-        let cap = self.prefix.len() + self.suffix.len() + input.len();
+    // Error is shown instead of suffix
+    fn build(&self, label: &str, input: &str, error: Option<&str>) -> String {
+        let cap = label.len() + self.prefix.len() + self.suffix.len() + input.len();
         let mut buf = String::with_capacity(cap);
+        buf.push_str(label);
         buf.push_str(self.prefix);
         buf.push_str(input);
-        buf.push_str(self.suffix);
+        if let Some(error) = error {
+            buf.push_str(" (");
+            buf.push_str(error);
+            buf.push(')');
+        } else {
+            buf.push_str(self.suffix);
+        }
         buf
     }
 
-    fn cursor_col(&self, input: &str) -> usize {
-        self.prefix_chars + input.chars().count() + 1 // Just after the input
+    fn cursor_col(&self, label: &str, input: &str) -> usize {
+        label.chars().count() + self.prefix_chars + input.chars().count() + 1 // Just after the input
     }
 }
 
@@ -350,6 +820,8 @@ pub struct Prompt<'a, W: Write> {
     hl: &'a mut Highlighting,
     sb: &'a mut StatusBar,
     empty_is_cancel: bool,
+    label: String,         // Shown before prompt to indicate mode of action
+    error: Option<String>, // Shown after input when action fails
 }
 
 impl<'a, W: Write> Prompt<'a, W> {
@@ -366,36 +838,79 @@ impl<'a, W: Write> Prompt<'a, W> {
             hl,
             sb,
             empty_is_cancel,
+            label: String::new(),
+            error: None,
         }
     }
 
     fn render_screen(&mut self, input: &str, template: &PromptTemplate<'_>) -> Result<()> {
-        self.screen.set_info_message(template.build(input));
+        let message = template.build(&self.label, input, self.error.as_deref());
+        if self.error.is_some() {
+            self.screen.set_error_message(message);
+        } else {
+            self.screen.set_info_message(message);
+        }
         self.sb.update_from_buf(self.buf);
-        self.screen.render(self.buf, &mut self.hl, self.sb)?;
+        self.screen.render(self.buf, self.hl, self.sb)?;
 
-        let row = self.screen.rows() + 2;
-        let col = template.cursor_col(input);
+        let row = self.screen.message_row();
+        let col = template.cursor_col(&self.label, input);
         self.screen.force_set_cursor(row, col)?;
 
         self.sb.redraw = false;
         Ok(())
     }
 
-    pub fn run<A, S, I>(&mut self, prompt: S, mut input: I) -> Result<PromptResult>
+    pub fn run<A, S, I>(&mut self, prompt: S, input: I) -> Result<PromptResult>
     where
         A: Action,
         S: AsRef<str>,
         I: Iterator<Item = Result<InputSeq>>,
     {
         let mut action = A::new(self);
+        let result = self.read_input(&mut action, prompt.as_ref(), input)?;
+        action.on_end(self, result)
+    }
+
+    // Read the query of query replace with the same keys as text search. Returns the query and its
+    // matcher. Cursor is moved back since replacing starts from the original position
+    pub fn read_query<S, I>(&mut self, prompt: S, input: I) -> Result<Option<(String, Matcher)>>
+    where
+        S: AsRef<str>,
+        I: Iterator<Item = Result<InputSeq>>,
+    {
+        let mut search = TextSearch::new(self);
+        let result = self.read_input(&mut search, prompt.as_ref(), input)?;
+        search.cleanup_match_highlight(self);
+        search.restore(self);
+
+        let query = match result {
+            PromptResult::Input(query) => query,
+            PromptResult::Canceled => return Ok(None),
+        };
+        let mut matcher = search.matcher;
+        if let Err(msg) = matcher.compile(&query) {
+            self.screen.set_error_message(msg);
+            return Ok(None);
+        }
+        Ok(Some((query, matcher)))
+    }
+
+    fn read_input<A, I>(
+        &mut self,
+        action: &mut A,
+        prompt: &str,
+        mut input: I,
+    ) -> Result<PromptResult>
+    where
+        A: Action,
+        I: Iterator<Item = Result<InputSeq>>,
+    {
         let mut buf = String::new();
         let mut canceled = false;
 
         let template = {
-            let mut it = prompt.as_ref().splitn(2, "{}");
-            let prefix = it.next().unwrap();
-            let suffix = it.next().unwrap();
+            let (prefix, suffix) = prompt.split_once("{}").unwrap();
             PromptTemplate::new(prefix, suffix)
         };
 
@@ -415,7 +930,7 @@ impl<'a, W: Write> Prompt<'a, W> {
             let prev_len = buf.len();
 
             match (&seq.key, seq.ctrl) {
-                (Unidentified, ..) => continue,
+                (Unidentified, ..) | (FocusIn, ..) | (Idle, ..) => continue,
                 (Key(b'h'), true) | (Key(0x7f), ..) | (DeleteKey, ..) if !buf.is_empty() => {
                     buf.pop();
                 }
@@ -438,8 +953,9 @@ impl<'a, W: Write> Prompt<'a, W> {
                         }
                     }
                 }
-                (Key(b), false) => buf.push(*b as char),
-                (Utf8Key(c), false) => buf.push(*c),
+                (Key(b), false) if !seq.alt => buf.push(*b as char),
+                (Utf8Key(c), false) if !seq.alt => buf.push(*c),
+                (Paste(text), ..) => buf.push_str(text.lines().next().unwrap_or("")), // Prompt is one line
                 _ => {}
             }
 
@@ -459,6 +975,117 @@ impl<'a, W: Write> Prompt<'a, W> {
             PromptResult::Input(buf)
         };
 
-        action.on_end(self, result)
+        Ok(result)
+    }
+
+    fn highlight_replaced_match(&mut self, start: (usize, usize), end: (usize, usize)) {
+        if let Some(line) = self.hl.clear_previous_match() {
+            self.screen.set_dirty_start(line);
+        }
+        let rows = self.buf.rows();
+        self.hl.set_matches(vec![RegionHighlight {
+            hl: Highlight::Search,
+            start: (rows[start.1].render_idx_from_cx(start.0), start.1),
+            end: (rows[end.1].render_idx_from_cx(end.0), end.1),
+        }]);
+        self.hl.needs_update = true;
+        self.screen.set_dirty_start(start.1);
+    }
+
+    fn ask_replace<I>(
+        &mut self,
+        template: &PromptTemplate<'_>,
+        input: &mut I,
+    ) -> Result<ReplaceAnswer>
+    where
+        I: Iterator<Item = Result<InputSeq>>,
+    {
+        use KeySeq::*;
+
+        while let Some(seq) = input.next() {
+            if self.screen.maybe_resize(&mut *input)? {
+                self.screen.set_dirty_start(self.screen.rowoff);
+                self.sb.redraw = true;
+                self.render_screen("", template)?;
+                continue;
+            }
+
+            let seq = seq?;
+            match (&seq.key, seq.ctrl) {
+                (Key(b'y'), false) | (Key(b' '), false) => return Ok(ReplaceAnswer::Yes),
+                (Key(b'n'), false) | (Key(0x7f), ..) | (DeleteKey, ..) => {
+                    return Ok(ReplaceAnswer::No)
+                }
+                (Key(b'!'), false) => return Ok(ReplaceAnswer::All),
+                (Key(b'q'), ..) | (Key(b'g'), true) | (Key(0x1b), ..) | (Key(b'\r'), ..) => {
+                    return Ok(ReplaceAnswer::Quit)
+                }
+                _ => {} // Ignore other keys
+            }
+        }
+
+        Ok(ReplaceAnswer::Quit)
+    }
+
+    // Replace matches of the query after cursor one by one asking y/n/!/q for each match like Emacs'
+    // query-replace. All replacements are merged into one undo entry. Returns how many matches were
+    // replaced.
+    pub fn query_replace<I>(
+        &mut self,
+        query: &str,
+        matcher: &Matcher,
+        replacement: &str,
+        mut input: I,
+    ) -> Result<usize>
+    where
+        I: Iterator<Item = Result<InputSeq>>,
+    {
+        self.label = matcher.label();
+        let question = format!(
+            "Replace '{}' with '{}'? (y: replace, n: skip, !: replace all, q: quit)",
+            query, replacement,
+        );
+        let template = PromptTemplate::new(&question, "");
+        let mut count = 0;
+        let mut replace_all = false;
+        let mut pos = self.buf.cursor();
+
+        while let Some((start, end)) = self
+            .buf
+            .find_forward(pos, |line, off| matcher.find_in_line(line, query, off))
+        {
+            self.buf.set_cursor(start.0, start.1);
+
+            if !replace_all {
+                self.highlight_replaced_match(start, end);
+                self.render_screen("", &template)?;
+                match self.ask_replace(&template, &mut input)? {
+                    ReplaceAnswer::Yes => {}
+                    ReplaceAnswer::No => {
+                        pos = end;
+                        continue;
+                    }
+                    ReplaceAnswer::All => replace_all = true,
+                    ReplaceAnswer::Quit => break,
+                }
+            }
+
+            self.buf.replace_range_merged(start, end, replacement);
+            self.hl.needs_update = true;
+            self.screen.set_dirty_start(start.1);
+            count += 1;
+            pos = self.buf.cursor(); // Do not match to the replaced text again
+        }
+
+        if count > 0 {
+            self.buf.close_undo_point();
+        }
+        if let Some(line) = self.hl.clear_previous_match() {
+            self.hl.needs_update = true;
+            self.screen.set_dirty_start(line);
+        }
+        self.sb.redraw = true;
+
+        Ok(count)
     }
 }
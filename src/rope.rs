@@ -0,0 +1,355 @@
+use crate::row::Row;
+use std::ops;
+
+// Chunk is split into two when its text grows larger than twice of this size by editing
+const CHUNK_SIZE: usize = 8 * 1024;
+
+// Run of whole lines stored in one string. Each line is followed by '\n' so that a line is always
+// a contiguous slice of the string
+#[derive(Default)]
+struct Chunk {
+    text: String,
+    starts: Vec<u32>, // Byte offset of each line in the text
+}
+
+impl Chunk {
+    fn with_capacity(cap: usize) -> Self {
+        Self {
+            text: String::with_capacity(cap),
+            starts: vec![],
+        }
+    }
+
+    fn len(&self) -> usize {
+        self.starts.len()
+    }
+
+    // Byte range of the line including the trailing '\n'
+    fn line_range(&self, i: usize) -> ops::Range<usize> {
+        let start = self.starts[i] as usize;
+        let end = self
+            .starts
+            .get(i + 1)
+            .map_or(self.text.len(), |&s| s as usize);
+        start..end
+    }
+
+    fn line(&self, i: usize) -> &str {
+        let r = self.line_range(i);
+        &self.text[r.start..r.end - 1]
+    }
+
+    fn push(&mut self, line: &str) {
+        self.starts.push(self.text.len() as u32);
+        self.text.push_str(line);
+        self.text.push('\n');
+    }
+
+    fn shift_starts(&mut self, from: usize, added: usize, removed: usize) {
+        for start in self.starts[from..].iter_mut() {
+            *start = (*start as usize + added - removed) as u32;
+        }
+    }
+}
+
+// Text of buffer as rope of line chunks. Lines are stored in chunks of a few kilobytes and each
+// line is borrowed as `&Row` from its chunk. Editing a line modifies its chunk in place so neither
+// memory per line nor a copy of the whole text is necessary
+#[derive(Default)]
+pub struct Rope {
+    chunks: Vec<Chunk>,
+    first_lines: Vec<usize>, // Index of the first line of each chunk
+    len: usize,
+}
+
+impl Rope {
+    // Split text into lines. '\r' before newline is removed only when `strip_cr` is true
+    pub fn from_text(text: &str, strip_cr: bool) -> Self {
+        let mut rope = Self::default();
+        let mut start = 0;
+        while start < text.len() {
+            let end = text[start..].find('\n').map_or(text.len(), |i| start + i);
+            let line_end = if strip_cr && end < text.len() {
+                end - 1
+            } else {
+                end
+            };
+            rope.push_loaded(&text[start..line_end]);
+            start = end + 1;
+        }
+        rope
+    }
+
+    pub fn from_lines<S: AsRef<str>, I: Iterator<Item = S>>(lines: I) -> Self {
+        let mut rope = Self::default();
+        for line in lines {
+            rope.push_loaded(line.as_ref());
+        }
+        rope
+    }
+
+    // Fill chunks up to their capacity on loading text so that strings are not reallocated
+    fn push_loaded(&mut self, line: &str) {
+        let full = match self.chunks.last() {
+            Some(c) => c.text.len() + line.len() + 1 > c.text.capacity(),
+            None => true,
+        };
+        if full {
+            if let Some(chunk) = self.chunks.last_mut() {
+                chunk.starts.shrink_to_fit();
+            }
+            self.chunks
+                .push(Chunk::with_capacity(CHUNK_SIZE.max(line.len() + 1)));
+            self.first_lines.push(self.len);
+        }
+        self.chunks.last_mut().unwrap().push(line);
+        self.len += 1;
+    }
+
+    pub fn len(&self) -> usize {
+        self.len
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len == 0
+    }
+
+    // Chunk index and line index in the chunk of the line
+    fn locate(&self, y: usize) -> (usize, usize) {
+        let c = self.first_lines.partition_point(|&first| first <= y) - 1;
+        (c, y - self.first_lines[c])
+    }
+
+    pub fn get(&self, y: usize) -> Option<&Row> {
+        if y < self.len {
+            let (c, i) = self.locate(y);
+            Some(Row::new(self.chunks[c].line(i)))
+        } else {
+            None
+        }
+    }
+
+    pub fn last(&self) -> Option<&Row> {
+        self.get(self.len.wrapping_sub(1))
+    }
+
+    pub fn iter(&self) -> Iter<'_> {
+        Iter {
+            rope: self,
+            chunk: 0,
+            line: 0,
+            remaining: self.len,
+        }
+    }
+
+    // Whole text where each line ends with '\n'. Chunks are already in this form
+    pub fn concat(&self) -> String {
+        let mut text = String::with_capacity(self.chunks.iter().map(|c| c.text.len()).sum());
+        for chunk in self.chunks.iter() {
+            text.push_str(&chunk.text);
+        }
+        text
+    }
+
+    // Replace the byte range in the line with the string
+    fn splice(&mut self, y: usize, range: ops::Range<usize>, s: &str) {
+        let (c, i) = self.locate(y);
+        let chunk = &mut self.chunks[c];
+        let start = chunk.starts[i] as usize;
+        let removed = range.len();
+        chunk
+            .text
+            .replace_range(start + range.start..start + range.end, s);
+        chunk.shift_starts(i + 1, s.len(), removed);
+        self.split_if_large(c);
+    }
+
+    fn split_if_large(&mut self, c: usize) {
+        let chunk = &mut self.chunks[c];
+        if chunk.text.len() <= CHUNK_SIZE * 2 || chunk.len() < 2 {
+            return;
+        }
+        // Split at the line boundary nearest to the middle
+        let half = (chunk.text.len() / 2) as u32;
+        let at = chunk
+            .starts
+            .partition_point(|&s| s < half)
+            .clamp(1, chunk.len() - 1);
+        let offset = chunk.starts[at];
+        let text = chunk.text.split_off(offset as usize);
+        let starts = chunk
+            .starts
+            .split_off(at)
+            .iter()
+            .map(|s| s - offset)
+            .collect();
+        self.chunks.insert(c + 1, Chunk { text, starts });
+        self.first_lines.insert(c + 1, self.first_lines[c] + at);
+    }
+
+    fn shift_first_lines(&mut self, after: usize, added: usize, removed: usize) {
+        for first in self.first_lines[after + 1..].iter_mut() {
+            *first = *first + added - removed;
+        }
+    }
+
+    pub fn insert_str(&mut self, y: usize, x: usize, s: &str) {
+        let row = &self[y];
+        let idx = if row.len() <= x {
+            row.buffer().len()
+        } else {
+            row.byte_idx_of(x)
+        };
+        self.splice(y, idx..idx, s);
+    }
+
+    pub fn insert_char(&mut self, y: usize, x: usize, c: char) {
+        self.insert_str(y, x, c.encode_utf8(&mut [0; 4]));
+    }
+
+    pub fn remove_char(&mut self, y: usize, x: usize) {
+        let row = &self[y];
+        let idx = row.byte_idx_of(x);
+        let end = idx + row.char_at(x).len_utf8();
+        self.splice(y, idx..end, "");
+    }
+
+    pub fn append(&mut self, y: usize, s: &str) {
+        let len = self[y].buffer().len();
+        self.splice(y, len..len, s);
+    }
+
+    pub fn truncate(&mut self, y: usize, at: usize) {
+        let row = &self[y];
+        if at < row.len() {
+            let range = row.byte_idx_of(at)..row.buffer().len();
+            self.splice(y, range, "");
+        }
+    }
+
+    pub fn remove(&mut self, y: usize, start: usize, end: usize) {
+        if start < end {
+            let row = &self[y];
+            let range = row.byte_idx_of(start)..row.byte_idx_of(end);
+            self.splice(y, range, "");
+        }
+    }
+
+    pub fn push(&mut self, line: &str) {
+        if self.chunks.is_empty() {
+            self.chunks.push(Chunk::default());
+            self.first_lines.push(0);
+        }
+        let c = self.chunks.len() - 1;
+        self.chunks[c].push(line);
+        self.len += 1;
+        self.split_if_large(c);
+    }
+
+    pub fn insert(&mut self, y: usize, line: &str) {
+        if y == self.len {
+            self.push(line);
+            return;
+        }
+        let (c, i) = self.locate(y);
+        let chunk = &mut self.chunks[c];
+        let start = chunk.starts[i] as usize;
+        chunk.text.insert(start, '\n');
+        chunk.text.insert_str(start, line);
+        chunk.starts.insert(i, start as u32);
+        chunk.shift_starts(i + 1, line.len() + 1, 0);
+        self.shift_first_lines(c, 1, 0);
+        self.len += 1;
+        self.split_if_large(c);
+    }
+
+    pub fn remove_line(&mut self, y: usize) {
+        let (c, i) = self.locate(y);
+        let chunk = &mut self.chunks[c];
+        let range = chunk.line_range(i);
+        let removed = range.len();
+        chunk.text.replace_range(range, "");
+        chunk.starts.remove(i);
+        chunk.shift_starts(i, 0, removed);
+        let empty = chunk.starts.is_empty();
+        self.shift_first_lines(c, 0, 1);
+        self.len -= 1;
+        if empty {
+            self.chunks.remove(c);
+            self.first_lines.remove(c);
+        }
+    }
+
+    pub fn pop(&mut self) {
+        if self.len > 0 {
+            self.remove_line(self.len - 1);
+        }
+    }
+}
+
+impl ops::Index<usize> for Rope {
+    type Output = Row;
+
+    fn index(&self, y: usize) -> &Row {
+        match self.get(y) {
+            Some(row) => row,
+            None => panic!("line {} is out of {} lines", y, self.len),
+        }
+    }
+}
+
+impl<'a> IntoIterator for &'a Rope {
+    type Item = &'a Row;
+    type IntoIter = Iter<'a>;
+
+    fn into_iter(self) -> Iter<'a> {
+        self.iter()
+    }
+}
+
+pub struct Iter<'a> {
+    rope: &'a Rope,
+    chunk: usize,
+    line: usize,
+    remaining: usize,
+}
+
+impl<'a> Iterator for Iter<'a> {
+    type Item = &'a Row;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.remaining == 0 {
+            return None;
+        }
+        let mut chunk = &self.rope.chunks[self.chunk];
+        if self.line == chunk.len() {
+            self.chunk += 1;
+            self.line = 0;
+            chunk = &self.rope.chunks[self.chunk];
+        }
+        let line = chunk.line(self.line);
+        self.line += 1;
+        self.remaining -= 1;
+        Some(Row::new(line))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+
+    // Skip lines without visiting each of them
+    fn nth(&mut self, n: usize) -> Option<Self::Item> {
+        if n >= self.remaining {
+            self.remaining = 0;
+            return None;
+        }
+        let y = self.rope.len - self.remaining + n;
+        let (chunk, line) = self.rope.locate(y);
+        self.chunk = chunk;
+        self.line = line;
+        self.remaining -= n;
+        self.next()
+    }
+}
+
+impl<'a> ExactSizeIterator for Iter<'a> {}
@@ -1,191 +1,171 @@
-use crate::error::{Error, Result};
+use std::borrow::Cow;
+use std::fmt::Write;
 use std::ops;
 use unicode_width::UnicodeWidthChar;
 
 const TAB_STOP: usize = 8;
 
-#[derive(Default)]
-pub struct Row {
-    buf: String,
-    render: String,
-    // Cache of byte indices of characters in `buf`. This will be empty when `buf` only contains
-    // single byte characters not to allocate memory.
-    indices: Vec<usize>,
+// Tab is not considered as control character since it is rendered as spaces
+fn is_control(c: char) -> bool {
+    c != '\t' && c.is_control()
 }
 
-impl Row {
-    pub fn empty() -> Row {
-        Row {
-            buf: "".to_string(),
-            render: "".to_string(),
-            indices: Vec::with_capacity(0),
+// Control character is rendered in caret notation (e.g. ^L) when it can be input with Ctrl key.
+// Otherwise it is rendered as its code point in hex (e.g. <1b>)
+fn is_caret_notation(c: char) -> bool {
+    ('\x01'..='\x1a').contains(&c)
+}
+
+fn render_control_char(c: char, render: &mut String) {
+    if is_caret_notation(c) {
+        render.push('^');
+        render.push((c as u8 + b'@') as char);
+    } else {
+        write!(render, "<{:02x}>", c as u32).unwrap();
+    }
+}
+
+// Display width of the character at the display column
+fn char_width(c: char, rx: usize) -> usize {
+    if c == '\t' {
+        TAB_STOP - (rx % TAB_STOP)
+    } else if is_control(c) {
+        if is_caret_notation(c) {
+            2
+        } else {
+            4
         }
+    } else {
+        c.width_cjk().unwrap()
     }
+}
+
+// Line of text. It borrows the line stored in `Rope` so that no memory is allocated per line
+#[repr(transparent)]
+pub struct Row(str);
 
-    pub fn new<S: Into<String>>(line: S) -> Result<Row> {
-        let mut row = Row {
-            buf: line.into(),
-            render: "".to_string(),
-            indices: Vec::with_capacity(0),
-        };
-        row.update_render()?;
-        Ok(row)
+impl Row {
+    pub fn new(line: &str) -> &Row {
+        // Safe since Row is a transparent wrapper of str as std::path::Path is of OsStr
+        unsafe { &*(line as *const str as *const Row) }
+    }
+
+    fn buf(&self) -> &str {
+        &self.0
     }
 
     // Returns number of characters
     pub fn len(&self) -> usize {
-        if self.indices.is_empty() {
-            self.buf.len()
+        if self.0.is_ascii() {
+            self.0.len()
         } else {
-            self.indices.len()
+            self.0.chars().count()
         }
     }
 
     pub fn byte_idx_of(&self, char_idx: usize) -> usize {
-        let len = self.indices.len();
-        if len == 0 {
-            char_idx
-        } else if len == char_idx {
-            self.buf.len()
-        } else {
-            self.indices[char_idx]
+        if self.0.is_ascii() {
+            return char_idx;
         }
+        self.0
+            .char_indices()
+            .nth(char_idx)
+            .map_or(self.0.len(), |(idx, _)| idx)
     }
 
     pub fn char_idx_of(&self, byte_idx: usize) -> usize {
-        if self.indices.is_empty() {
-            return byte_idx;
-        }
-        if self.buf.len() == byte_idx {
-            return self.indices.len(); // When the byte index points after the last character
+        if self.0.is_ascii() {
+            byte_idx
+        } else {
+            self.0[..byte_idx].chars().count()
         }
-        self.indices
-            .iter()
-            .position(|bi| *bi == byte_idx)
-            .expect("byte index is not correct boundary of UTF-8")
     }
 
     pub fn buffer(&self) -> &str {
-        self.buf.as_str()
-    }
-
-    pub fn render_text(&self) -> &str {
-        self.render.as_str()
-    }
-
-    pub fn char_at(&self, at: usize) -> char {
-        self.char_at_checked(at).unwrap()
+        self.buf()
     }
 
-    pub fn char_at_checked(&self, at: usize) -> Option<char> {
-        self[at..].chars().next()
-    }
-
-    fn update_render(&mut self) -> Result<()> {
-        self.render.clear();
-        self.render.reserve(self.buf.len());
-        let mut index = 0;
-        let mut num_chars = 0;
-
-        for c in self.buf.chars() {
+    // Text is rendered as is unless it contains tabs or control characters. Otherwise render text
+    // is computed on each call not to keep it in memory
+    pub fn render_text(&self) -> Cow<'_, str> {
+        if !self.buf().chars().any(|c| c == '\t' || is_control(c)) {
+            return Cow::Borrowed(self.buf());
+        }
+        let mut render = String::with_capacity(self.buf().len());
+        let mut rx = 0;
+        for c in self.buf().chars() {
+            let width = char_width(c, rx);
             if c == '\t' {
-                loop {
-                    self.render.push(' ');
-                    index += 1;
-                    if index % TAB_STOP == 0 {
-                        break;
-                    }
+                for _ in 0..width {
+                    render.push(' ');
                 }
-            } else if let Some(width) = c.width_cjk() {
-                index += width;
-                self.render.push(c);
+            } else if is_control(c) {
+                render_control_char(c, &mut render);
             } else {
-                // Control sequences are valid for UTF-8 but they should not appear in text.
-                return Err(Error::ControlCharInText(c));
+                render.push(c);
             }
-            num_chars += 1;
+            rx += width;
         }
-
-        if num_chars == self.buf.len() {
-            // If number of chars is the same as byte length, this line includes no multi-byte char.
-            // Vector with zero capacity is guaranteed not to allocate heap memory.
-            self.indices = Vec::with_capacity(0)
-        } else {
-            self.indices.clear();
-            self.indices.reserve(num_chars);
-            for (idx, _) in self.buf.char_indices() {
-                self.indices.push(idx);
+        Cow::Owned(render)
+    }
+
+    // Ranges of control characters in render text. They are highlighted in distinct color
+    pub fn control_char_ranges(&self) -> impl Iterator<Item = ops::Range<usize>> + '_ {
+        let (mut rx, mut idx) = (0, 0);
+        self.buf().chars().filter_map(move |c| {
+            let width = char_width(c, rx);
+            rx += width;
+            let start = idx;
+            if c == '\t' || is_control(c) {
+                idx += width; // Rendered as spaces or notation of ASCII characters
+            } else {
+                idx += 1;
             }
-        }
-
-        Ok(())
-    }
-
-    pub fn rx_from_cx(&self, cx: usize) -> usize {
-        self[..cx].chars().fold(0, |rx, ch| {
-            if ch == '\t' {
-                // Proceed TAB_STOP spaces then subtract spaces by mod TAB_STOP
-                rx + TAB_STOP - (rx % TAB_STOP)
+            if is_control(c) {
+                Some(start..idx)
             } else {
-                rx + ch.width_cjk().unwrap()
+                None
             }
         })
     }
 
-    pub fn insert_char(&mut self, at: usize, c: char) {
-        if self.len() <= at {
-            self.buf.push(c);
-        } else {
-            self.buf.insert(self.byte_idx_of(at), c);
-        }
-        // TODO: More efficient update for self.render
-        self.update_render().unwrap();
-    }
-
-    pub fn insert_str<S: AsRef<str>>(&mut self, at: usize, s: S) {
-        if self.len() <= at {
-            self.buf.push_str(s.as_ref());
-        } else {
-            self.buf.insert_str(self.byte_idx_of(at), s.as_ref());
-        }
-        self.update_render().unwrap();
+    pub fn char_at(&self, at: usize) -> char {
+        self.char_at_checked(at).unwrap()
     }
 
-    pub fn delete_char(&mut self, at: usize) {
-        if at < self.len() {
-            self.buf.remove(self.byte_idx_of(at));
-            self.update_render().unwrap();
-        }
+    pub fn char_at_checked(&self, at: usize) -> Option<char> {
+        self[at..].chars().next()
     }
 
-    pub fn append<S: AsRef<str>>(&mut self, s: S) {
-        let s = s.as_ref();
-        if s.is_empty() {
-            return;
-        }
-        self.buf.push_str(s);
-        self.update_render().unwrap();
+    pub fn rx_from_cx(&self, cx: usize) -> usize {
+        self[..cx].chars().fold(0, |rx, ch| rx + char_width(ch, rx))
     }
 
-    pub fn truncate(&mut self, at: usize) {
-        if at < self.len() {
-            self.buf.truncate(self.byte_idx_of(at));
-            self.update_render().unwrap();
+    // Inverse of rx_from_cx. When the display column is at the middle of a wide character or a tab,
+    // index of the character is returned
+    pub fn cx_from_rx(&self, rx: usize) -> usize {
+        let mut cur = 0;
+        for (cx, ch) in self.buf().chars().enumerate() {
+            cur += char_width(ch, cur);
+            if cur > rx {
+                return cx;
+            }
         }
+        self.len()
     }
 
-    pub fn remove_char(&mut self, at: usize) {
-        self.buf.remove(self.byte_idx_of(at));
-        self.update_render().unwrap();
-    }
-
-    pub fn remove(&mut self, start: usize, end: usize) {
-        if start < end {
-            let start_idx = self.byte_idx_of(start);
-            let end_idx = self.byte_idx_of(end);
-            self.buf.drain(start_idx..end_idx);
-            self.update_render().unwrap();
-        }
+    // Index of character in `render` text. This is different from rx since rx is a display width
+    pub fn render_idx_from_cx(&self, cx: usize) -> usize {
+        let mut rx = 0;
+        self[..cx].chars().fold(0, |idx, ch| {
+            let width = char_width(ch, rx);
+            rx += width;
+            if ch == '\t' || is_control(ch) {
+                idx + width // Rendered as spaces or notation of ASCII characters
+            } else {
+                idx + 1
+            }
+        })
     }
 }
 
@@ -195,7 +175,7 @@ impl ops::Index<ops::Range<usize>> for Row {
     fn index(&self, r: ops::Range<usize>) -> &Self::Output {
         let start = self.byte_idx_of(r.start);
         let end = self.byte_idx_of(r.end);
-        &self.buf[start..end]
+        &self.buf()[start..end]
     }
 }
 
@@ -204,7 +184,7 @@ impl ops::Index<ops::RangeFrom<usize>> for Row {
 
     fn index(&self, r: ops::RangeFrom<usize>) -> &Self::Output {
         let start = self.byte_idx_of(r.start);
-        &self.buf[start..]
+        &self.buf()[start..]
     }
 }
 
@@ -213,7 +193,7 @@ impl ops::Index<ops::RangeTo<usize>> for Row {
 
     fn index(&self, r: ops::RangeTo<usize>) -> &Self::Output {
         let end = self.byte_idx_of(r.end);
-        &self.buf[..end]
+        &self.buf()[..end]
     }
 }
 
@@ -223,7 +203,7 @@ impl ops::Index<ops::RangeInclusive<usize>> for Row {
     fn index(&self, r: ops::RangeInclusive<usize>) -> &Self::Output {
         let start = self.byte_idx_of(*r.start());
         let end = self.byte_idx_of(*r.end());
-        &self.buf[start..=end]
+        &self.buf()[start..=end]
     }
 }
 
@@ -232,6 +212,6 @@ impl ops::Index<ops::RangeToInclusive<usize>> for Row {
 
     fn index(&self, r: ops::RangeToInclusive<usize>) -> &Self::Output {
         let end = self.byte_idx_of(r.end);
-        &self.buf[..=end]
+        &self.buf()[..=end]
     }
 }
@@ -0,0 +1,238 @@
+use std::fs::{self, File, Metadata, OpenOptions};
+use std::io::{self, Write};
+use std::os::unix::fs::{self as unix_fs, MetadataExt, OpenOptionsExt};
+use std::path::{Path, PathBuf};
+use std::process;
+use std::time::SystemTime;
+
+const PRIVATE_MODE: u32 = 0o600;
+
+// Metadata of file on disk. It is recorded when the file is loaded or saved to detect
+// modification by other programs such as `git checkout` or formatters
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub struct FileStamp {
+    modified: Option<SystemTime>,
+    len: u64,
+    dev: u64,
+    ino: u64, // Atomic save by other program replaces inode
+}
+
+impl FileStamp {
+    pub fn of<P: AsRef<Path>>(path: P) -> Option<Self> {
+        let meta = fs::metadata(path).ok()?;
+        Some(Self {
+            modified: meta.modified().ok(),
+            len: meta.len(),
+            dev: meta.dev(),
+            ino: meta.ino(),
+        })
+    }
+}
+
+// Temporary file is put in the same directory as the target so that renaming it is atomic. The
+// sequence number makes another name when a stale temporary file was left by crash
+fn temp_path(target: &Path, seq: usize) -> PathBuf {
+    let name = target
+        .file_name()
+        .map(|n| n.to_string_lossy())
+        .unwrap_or_default();
+    if seq == 0 {
+        target.with_file_name(format!(".{}.kiro-{}.tmp", name, process::id()))
+    } else {
+        target.with_file_name(format!(".{}.kiro-{}-{}.tmp", name, process::id(), seq))
+    }
+}
+
+fn create_temp(target: &Path, mode: u32) -> io::Result<(File, PathBuf)> {
+    let mut seq = 0;
+    loop {
+        let temp = temp_path(target, seq);
+        match OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .mode(mode)
+            .open(&temp)
+        {
+            Ok(f) => return Ok((f, temp)),
+            Err(err) if err.kind() == io::ErrorKind::AlreadyExists && seq < 100 => seq += 1,
+            Err(err) => return Err(err),
+        }
+    }
+}
+
+// Renaming is not durable until the directory entry is flushed
+fn sync_parent_dir(path: &Path) -> io::Result<()> {
+    let dir = match path.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    File::open(dir)?.sync_all()
+}
+
+// Overwrite the file directly. The file is truncated at first so it may be broken when writing
+// fails in the middle
+fn write_in_place(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    let mut f = File::create(path)?;
+    f.write_all(bytes)?;
+    f.flush()
+}
+
+fn write_temp(f: &mut File, bytes: &[u8], meta: Option<&Metadata>) -> io::Result<()> {
+    f.write_all(bytes)?;
+    if let Some(meta) = meta {
+        f.set_permissions(meta.permissions())?;
+    }
+    f.sync_all()
+}
+
+fn rename_temp(temp: &Path, target: &Path, written: io::Result<()>) -> io::Result<()> {
+    let renamed = written.and_then(|_| fs::rename(temp, target));
+    if renamed.is_err() {
+        let _ = fs::remove_file(temp);
+    }
+    renamed
+}
+
+// Write the bytes to the file atomically. The bytes are written to a temporary file then it is
+// renamed to the file. Symlink is followed to save the real file and mode and owner of the file
+// are preserved. When renaming is not possible (e.g. the directory is not writable or the owner
+// cannot be kept), the file is overwritten in place.
+//
+// The file was already replaced when flushing the directory fails. The error is returned as
+// warning in `Ok` not to report the save as failed.
+pub fn write_file(path: &Path, bytes: &[u8]) -> io::Result<Option<io::Error>> {
+    let target = match fs::canonicalize(path) {
+        Ok(target) => target,
+        // Dangling symlink. Writing to it creates the link target
+        Err(_) if fs::symlink_metadata(path).is_ok() => {
+            return write_in_place(path, bytes).map(|_| None)
+        }
+        Err(_) => path.to_path_buf(), // New file
+    };
+
+    let meta = fs::metadata(&target).ok();
+    if let Some(meta) = &meta {
+        // Renaming would break hard links. Read-only file should fail to be written as before
+        if meta.nlink() > 1 || meta.permissions().readonly() {
+            return write_in_place(&target, bytes).map(|_| None);
+        }
+    }
+
+    let (mut f, temp) = match create_temp(&target, 0o666) {
+        Ok(created) => created, // Mode is masked by umask
+        // Directory is not writable
+        Err(err) if err.kind() == io::ErrorKind::PermissionDenied => {
+            return write_in_place(&target, bytes).map(|_| None)
+        }
+        Err(err) => return Err(err),
+    };
+
+    if let Some(meta) = &meta {
+        // Changing owner requires privilege. Renaming would pass the file to this user, so the
+        // file is overwritten instead. Owner must be changed before mode since chown clears
+        // setuid and setgid bits
+        if unix_fs::fchown(&f, Some(meta.uid()), Some(meta.gid())).is_err() {
+            drop(f);
+            let _ = fs::remove_file(&temp);
+            return write_in_place(&target, bytes).map(|_| None);
+        }
+    }
+
+    let written = write_temp(&mut f, bytes, meta.as_ref());
+    rename_temp(&temp, &target, written)?;
+    Ok(sync_parent_dir(&target).err())
+}
+
+// Replace the file with a new file which is readable and writable only by its owner. This is for
+// files which contain unsaved text such as swap files. The path is never followed as symlink and
+// is never overwritten in place since other users may put a symlink to some file of this user
+pub fn replace_private_file(path: &Path, bytes: &[u8]) -> io::Result<()> {
+    if let Ok(meta) = fs::symlink_metadata(path) {
+        if !meta.file_type().is_file() {
+            return Err(io::Error::new(
+                io::ErrorKind::AlreadyExists,
+                format!("{:?} is not a regular file", path),
+            ));
+        }
+    }
+    // Temporary file is newly created so it is not a symlink
+    let (mut f, temp) = create_temp(path, PRIVATE_MODE)?;
+    let written = write_temp(&mut f, bytes, None);
+    rename_temp(&temp, path, written)
+}
+
+// How to back up the original file before the first save of each file in a session
+#[derive(Clone, Copy, PartialEq, Debug, Default)]
+pub enum BackupMode {
+    #[default]
+    Off,
+    Single,   // file~
+    Numbered, // file.~1~, file.~2~, ...
+}
+
+#[derive(Clone, PartialEq, Debug, Default)]
+pub struct Backup {
+    pub mode: BackupMode,
+    pub dir: Option<PathBuf>, // Put backup files in this directory instead of next to the files
+}
+
+fn with_suffix(path: &Path, suffix: &str) -> PathBuf {
+    let mut path = path.as_os_str().to_owned();
+    path.push(suffix);
+    PathBuf::from(path)
+}
+
+// Next number of numbered backup files. Numbers are never reused as Emacs does
+fn next_backup_number(base: &Path) -> io::Result<usize> {
+    let prefix = format!(
+        "{}.~",
+        base.file_name().unwrap_or_default().to_string_lossy()
+    );
+    let dir = match base.parent() {
+        Some(dir) if !dir.as_os_str().is_empty() => dir,
+        _ => Path::new("."),
+    };
+    let mut max = 0;
+    for entry in fs::read_dir(dir)? {
+        let name = entry?.file_name();
+        let num = name
+            .to_str()
+            .and_then(|n| n.strip_prefix(&prefix))
+            .and_then(|n| n.strip_suffix('~'))
+            .and_then(|n| n.parse().ok());
+        if let Some(num) = num {
+            max = max.max(num);
+        }
+    }
+    Ok(max + 1)
+}
+
+// Copy the file to its backup file. Nothing is done when the file does not exist yet
+pub fn backup(path: &Path, backup: &Backup) -> io::Result<()> {
+    if backup.mode == BackupMode::Off {
+        return Ok(());
+    }
+    let file = match fs::canonicalize(path) {
+        Ok(file) => file,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(()),
+        Err(err) => return Err(err),
+    };
+
+    // Backup file in backup directory is named after the absolute path of the file such as
+    // '!home!user!foo.txt~' not to conflict with backups of other files
+    let base = if let Some(dir) = &backup.dir {
+        fs::create_dir_all(dir)?;
+        dir.join(file.to_string_lossy().replace('/', "!"))
+    } else {
+        file.clone()
+    };
+    let dest = match backup.mode {
+        BackupMode::Single => with_suffix(&base, "~"),
+        BackupMode::Numbered => with_suffix(&base, &format!(".~{}~", next_backup_number(&base)?)),
+        BackupMode::Off => unreachable!(),
+    };
+
+    fs::copy(&file, &dest)
+        .map(|_| ())
+        .map_err(|err| io::Error::new(err.kind(), format!("{}: {}", dest.display(), err)))
+}
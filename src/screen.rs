@@ -1,13 +1,18 @@
+use crate::clipboard;
 use crate::error::{Error, Result};
 use crate::highlight::Highlighting;
 use crate::input::{InputSeq, KeySeq};
+use crate::layout::{Layout, Rect, SplitDir};
+use crate::rope::Rope;
 use crate::row::Row;
 use crate::signal::SigwinchWatcher;
 use crate::status_bar::StatusBar;
 use crate::term_color::{Color, TermColor};
-use crate::text_buffer::TextBuffer;
+use crate::text_buffer::{CursorDir, TextBuffer};
 use std::cmp;
+use std::env;
 use std::io::Write;
+use std::mem;
 use std::time::SystemTime;
 use unicode_width::UnicodeWidthChar;
 
@@ -15,9 +20,11 @@ pub const VERSION: &str = env!("CARGO_PKG_VERSION");
 pub const HELP: &str = "\
     Ctrl-Q                        : Quit
     Ctrl-S                        : Save to file
+    Alt-R                         : Reload file discarding changes
     Ctrl-O                        : Open text buffer
     Ctrl-X                        : Next text buffer
     Alt-X                         : Previous text buffer
+    Alt-K                         : Close text buffer
     Ctrl-P or UP                  : Move cursor up
     Ctrl-N or DOWN                : Move cursor down
     Ctrl-F or RIGHT               : Move cursor right
@@ -34,14 +41,31 @@ pub const HELP: &str = "\
     Alt->                         : Move cursor to bottom of file
     Ctrl-H or BACKSPACE           : Delete character
     Ctrl-D or DELETE              : Delete next character
-    Ctrl-W                        : Delete a word
-    Ctrl-J                        : Delete until head of line
-    Ctrl-K                        : Delete until end of line
+    Ctrl-W                        : Cut a word (or selected region)
+    Ctrl-J                        : Cut until head of line
+    Ctrl-K                        : Cut until end of line
+    Alt-W                         : Copy selected region
+    Ctrl-Y                        : Paste last cut or copied text
+    Alt-Y                         : Replace pasted text with previous cut text
+    Alt-C                         : Copy last cut or copied text to system clipboard
+    Alt-I                         : Paste text from system clipboard
     Ctrl-U                        : Undo last change
     Ctrl-R                        : Redo last undo change
-    Ctrl-G                        : Search text
+    Ctrl-G                        : Search text (Alt-R: regex, Alt-C: ignore/smart case)
+    Alt-G                         : Go to line (LINE, LINE:COL, +N or -N)
+    Alt-%                         : Replace text asking y, n, ! (all) or q for each match
     Ctrl-M                        : New line
+    Ctrl-I                        : Insert tab (or indent selected region)
+    Ctrl-SPACE                    : Set mark to start selecting region
     Ctrl-L                        : Refresh screen
+    Alt-L                         : Toggle line numbers (off, absolute, relative)
+    Alt-S                         : Toggle soft wrap of long lines
+    Alt-E                         : Convert line endings between LF and CRLF
+    Alt-Q                         : Insert next key as character (e.g. Ctrl-L as ^L)
+    Alt-2                         : Split window into upper and lower
+    Alt-3                         : Split window into left and right
+    Alt-0                         : Close current window
+    Alt-O                         : Move focus to next window
     Ctrl-?                        : Show this help";
 
 #[derive(PartialEq)]
@@ -124,13 +148,92 @@ impl DrawMessage {
     }
 }
 
+// Line numbers shown in gutter at left of text
+#[derive(PartialEq, Clone, Copy, Debug, Default)]
+pub enum LineNumbers {
+    #[default]
+    Off,
+    Absolute,
+    Relative, // Distance from cursor line. Cursor line shows its absolute number
+}
+
+impl LineNumbers {
+    pub fn next(self) -> Self {
+        match self {
+            LineNumbers::Off => LineNumbers::Absolute,
+            LineNumbers::Absolute => LineNumbers::Relative,
+            LineNumbers::Relative => LineNumbers::Off,
+        }
+    }
+}
+
+// Visual line on screen. In soft wrap mode, one row may be rendered in multiple lines
+struct ScreenLine {
+    file_row: usize,
+    start: usize, // Display column where the line starts
+    end: usize,   // Display column where the line ends (exclusive)
+    head: bool,   // True when the line is the first line of the row
+}
+
+// Split render text of the row into visual lines which fit in the width. Returns display columns
+// where each visual line starts. Double-width character which does not fit in the rest of the line
+// is moved to the next line.
+fn wrap_starts(row: &Row, width: usize) -> Vec<usize> {
+    let mut starts = vec![0];
+    let mut col = 0;
+    for c in row.render_text().chars() {
+        let w = c.width_cjk().unwrap_or(1);
+        if col + w - starts[starts.len() - 1] > width {
+            starts.push(col);
+        }
+        col += w;
+    }
+    starts
+}
+
+// Scroll state and area of a window. State of the focused window is held by `Screen` fields
+// directly and swapped with this when rendering other windows or moving focus.
+#[derive(Clone, Default)]
+struct View {
+    rx: usize,
+    rowoff: usize,
+    coloff: usize,
+    dirty_start: Option<usize>,
+    gutter_width: usize,
+    prev_cy: usize,
+    cursor_pos: (usize, usize),
+    top: usize,
+    left: usize,
+    num_cols: usize,
+    num_rows: usize,
+    bottom: bool,
+    right_edge: bool,
+    drawn_rows: usize,   // Number of lines at previous render of unfocused window
+    redraw_status: bool, // Status bar of unfocused window needs to be redrawn
+}
+
 pub struct Screen<W: Write> {
     output: W,
     // X coordinate in `render` text of rows
     rx: usize,
-    // Screen size
+    // Size of focused window. Status bar is not included in rows
     num_cols: usize,
     num_rows: usize,
+    // Position of focused window on terminal
+    top: usize,
+    left: usize,
+    // Window at bottom of terminal extends to the message bar line while no message is shown
+    bottom: bool,
+    // Separator is drawn at right of window unless it touches right edge of terminal
+    right_edge: bool,
+    // Terminal size
+    term_cols: usize,
+    term_rows: usize,
+    layout: Layout,
+    views: Vec<View>,
+    focus: usize,
+    // Output of rendering unfocused windows. It is flushed with rendering the focused window
+    pending: Vec<u8>,
     message: Option<StatusMessage>,
     draw_message: DrawMessage,
     // Dirty line which requires rendering update. After this line must be updated since
@@ -139,6 +242,15 @@ pub struct Screen<W: Write> {
     // Watch resize signal
     sigwinch: SigwinchWatcher,
     term_color: TermColor,
+    line_numbers: LineNumbers,
+    // Width of line number gutter including one space margin. 0 when line numbers are off
+    gutter_width: usize,
+    // Cursor line at previous render. Line numbers need to be redrawn when cursor line changes
+    prev_cy: usize,
+    // Long rows are wrapped into multiple lines instead of scrolling horizontally
+    soft_wrap: bool,
+    // Cursor position on screen (0-based line and display column excluding gutter)
+    cursor_pos: (usize, usize),
     pub cursor_moved: bool,
     pub rowoff: usize, // Row scroll offset
     pub coloff: usize, // Column scroll offset
@@ -164,6 +276,11 @@ impl<W: Write> Screen<W> {
         // https://www.xfree86.org/current/ctlseqs.html#The%20Alternate%20Screen%20Buffer
         // https://invisible-island.net/xterm/ctlseqs/ctlseqs.html (CSI ? Pm h)
         output.write(b"\x1b[?1049h")?;
+        // Enable bracketed paste mode to receive pasted text at once
+        // https://invisible-island.net/xterm/ctlseqs/ctlseqs.html#h2-Bracketed-Paste-Mode
+        output.write(b"\x1b[?2004h")?;
+        // Enable focus reporting to check files modified while the terminal is not focused
+        output.write(b"\x1b[?1004h")?;
 
         Ok(Self {
             output,
@@ -171,6 +288,16 @@ impl<W: Write> Screen<W> {
             num_cols: w,
             // Screen height is 1 line less than window height due to status bar
             num_rows: h.saturating_sub(2),
+            top: 0,
+            left: 0,
+            bottom: true,
+            right_edge: true,
+            term_cols: w,
+            term_rows: h,
+            layout: Layout::Window(0),
+            views: vec![View::default()],
+            focus: 0,
+            pending: vec![],
             message: Some(StatusMessage::new(
                 "Ctrl-? for help",
                 StatusMessageKind::Info,
@@ -179,6 +306,11 @@ impl<W: Write> Screen<W> {
             dirty_start: Some(0), // Render entire screen at first paint
             sigwinch: SigwinchWatcher::new()?,
             term_color: TermColor::from_env(),
+            line_numbers: LineNumbers::Off,
+            gutter_width: 0,
+            prev_cy: 0,
+            soft_wrap: false,
+            cursor_pos: (0, 0),
             cursor_moved: true,
             rowoff: 0,
             coloff: 0,
@@ -199,8 +331,34 @@ impl<W: Write> Screen<W> {
         line.chars().skip(self.coloff).take(self.num_cols).collect()
     }
 
+    fn draw_separator<B: Write>(&self, mut buf: B) -> Result<()> {
+        buf.write(self.term_color.sequence(Color::NonText))?;
+        buf.write(b"|")?;
+        buf.write(self.term_color.sequence(Color::Reset))?;
+        Ok(())
+    }
+
+    // Clear the rest of line in window. `drawn` is width of text already drawn in the line
+    fn clear_rest<B: Write>(&self, mut buf: B, drawn: usize) -> Result<()> {
+        if self.right_edge {
+            // Erases the part of the line to the right of the cursor. http://vt100.net/docs/vt100-ug/chapter3.html#EL
+            buf.write(b"\x1b[K")?;
+            return Ok(());
+        }
+        // Erasing line would clear the window at right
+        for _ in drawn..self.num_cols {
+            buf.write(b" ")?;
+        }
+        self.draw_separator(buf)
+    }
+
     fn draw_status_bar<B: Write>(&self, mut buf: B, status_bar: &StatusBar) -> Result<()> {
-        write!(buf, "\x1b[{}H", self.rows() + 1)?;
+        let row = self.top + self.rows() + 1;
+        if !self.right_edge {
+            write!(buf, "\x1b[{};{}H", row, self.left + self.num_cols + 1)?;
+            self.draw_separator(&mut buf)?;
+        }
+        write!(buf, "\x1b[{};{}H", row, self.left + 1)?;
 
         buf.write(self.term_color.sequence(Color::Invert))?;
 
@@ -235,9 +393,9 @@ impl<W: Write> Screen<W> {
 
     fn draw_message_bar<B: Write>(&self, mut buf: B, message: &StatusMessage) -> Result<()> {
         // TODO: Handle multi-byte chars correctly
-        let text = &message.text[..cmp::min(message.text.len(), self.num_cols)];
+        let text = &message.text[..cmp::min(message.text.len(), self.term_cols)];
 
-        write!(buf, "\x1b[{}H", self.num_rows + 2)?;
+        write!(buf, "\x1b[{}H", self.term_rows)?;
 
         if message.kind == StatusMessageKind::Error {
             buf.write(self.term_color.sequence(Color::RedBg))?;
@@ -297,40 +455,68 @@ impl<W: Write> Screen<W> {
         Ok(())
     }
 
+    fn draw_line_number<B: Write>(&self, mut buf: B, file_row: usize, cy: usize) -> Result<()> {
+        let (num, color) = if file_row == cy {
+            (file_row + 1, Color::Yellow)
+        } else if self.line_numbers == LineNumbers::Relative {
+            (
+                cmp::max(file_row, cy) - cmp::min(file_row, cy),
+                Color::NonText,
+            )
+        } else {
+            (file_row + 1, Color::NonText)
+        };
+        buf.write(self.term_color.sequence(color))?;
+        write!(buf, "{:>width$} ", num, width = self.gutter_width - 1)?;
+        buf.write(self.term_color.sequence(Color::Reset))?;
+        Ok(())
+    }
+
     fn draw_rows<B: Write>(
         &self,
         mut buf: B,
         dirty_start: usize,
-        rows: &[Row],
+        rows: &Rope,
+        cy: usize,
         hl: &Highlighting,
     ) -> Result<()> {
         let row_len = rows.len();
 
         buf.write(self.term_color.sequence(Color::Reset))?;
 
-        for y in 0..self.rows() {
-            let file_row = y + self.rowoff;
+        for (y, line) in self.screen_lines(rows).iter().enumerate() {
+            let file_row = line.file_row;
 
             if file_row < dirty_start {
                 continue;
             }
 
             // H: Command to move cursor. Here \x1b[H is the same as \x1b[1;1H
-            write!(buf, "\x1b[{}H", y + 1)?;
+            write!(buf, "\x1b[{};{}H", self.top + y + 1, self.left + 1)?;
 
+            let mut drawn = 1;
             if file_row >= row_len {
                 buf.write(self.term_color.sequence(Color::NonText))?;
                 buf.write(b"~")?;
             } else {
                 let row = &rows[file_row];
+                drawn = 0;
+
+                if self.gutter_width > 0 {
+                    if line.head {
+                        self.draw_line_number(&mut buf, file_row, cy)?;
+                    } else {
+                        write!(buf, "{:width$}", "", width = self.gutter_width)?;
+                    }
+                }
 
                 let mut col = 0;
                 let mut prev_color = Color::Reset;
                 for (c, hl) in row.render_text().chars().zip(hl.lines[file_row].iter()) {
                     col += c.width_cjk().unwrap_or(1);
-                    if col <= self.coloff {
+                    if col <= line.start {
                         continue;
-                    } else if col > self.num_cols + self.coloff {
+                    } else if col > line.end {
                         break;
                     }
 
@@ -344,15 +530,16 @@ impl<W: Write> Screen<W> {
                     }
 
                     write!(buf, "{}", c)?;
+                    drawn = col - line.start;
                 }
+                drawn += self.gutter_width;
             }
 
             // Ensure to end with reset color sequence. Otherwise, when background color is highlighted
             // at the end of line, highlight will continue to the end of last column in terminal window.
             buf.write(self.term_color.sequence(Color::Reset))?;
 
-            // Erases the part of the line to the right of the cursor. http://vt100.net/docs/vt100-ug/chapter3.html#EL
-            buf.write(b"\x1b[K")?;
+            self.clear_rest(&mut buf, drawn)?;
         }
 
         Ok(())
@@ -364,11 +551,12 @@ impl<W: Write> Screen<W> {
         hl: &Highlighting,
         status_bar: &StatusBar,
     ) -> Result<()> {
-        let cursor_row = text_buf.cy() - self.rowoff + 1;
-        let cursor_col = self.rx - self.coloff + 1;
+        let cursor_row = self.top + self.cursor_pos.0 + 1;
+        let cursor_col = self.left + self.cursor_pos.1 + 1 + self.gutter_width;
         let draw_message = self.draw_message;
 
         if self.dirty_start.is_none()
+            && self.pending.is_empty()
             && !status_bar.redraw
             && draw_message == DrawMessage::DoNothing
         {
@@ -384,9 +572,11 @@ impl<W: Write> Screen<W> {
         // This command must be flushed at first otherwise cursor may move before being hidden
         self.write_flush(b"\x1b[?25l")?;
 
-        let mut buf = Vec::with_capacity((self.rows() + 2) * self.num_cols);
+        // Output of other windows is written at once
+        let mut buf = mem::take(&mut self.pending);
+        buf.reserve((self.rows() + 2) * self.num_cols);
         if let Some(s) = self.dirty_start {
-            self.draw_rows(&mut buf, s, text_buf.rows(), hl)?;
+            self.draw_rows(&mut buf, s, text_buf.rows(), text_buf.cy(), hl)?;
         }
 
         // When message bar opens/closes, position of status bar is changed
@@ -427,7 +617,7 @@ impl<W: Write> Screen<W> {
         coloff
     }
 
-    fn do_scroll(&mut self, rows: &[Row], (cx, cy): (usize, usize)) {
+    fn do_scroll(&mut self, rows: &Rope, (cx, cy): (usize, usize)) {
         let prev_rowoff = self.rowoff;
         let prev_coloff = self.coloff;
 
@@ -447,11 +637,18 @@ impl<W: Write> Screen<W> {
             // Scroll down when cursor is below the bottom of screen
             self.rowoff = cy - self.rows() + 1;
         }
-        if self.rx < self.coloff {
-            self.coloff = self.rx;
-        }
-        if self.rx >= self.coloff + self.num_cols {
-            self.coloff = self.next_coloff(self.rx - self.num_cols + 1, &rows[cy]);
+
+        if self.soft_wrap {
+            self.scroll_wrapped_lines(rows, cy);
+        } else {
+            if self.rx < self.coloff {
+                self.coloff = self.rx;
+            }
+            let cols = self.text_cols();
+            if self.rx >= self.coloff + cols {
+                self.coloff = self.next_coloff(self.rx - cols + 1, &rows[cy]);
+            }
+            self.cursor_pos = (cy - self.rowoff, self.rx - self.coloff);
         }
 
         if prev_rowoff != self.rowoff || prev_coloff != self.coloff {
@@ -463,14 +660,152 @@ impl<W: Write> Screen<W> {
         }
     }
 
-    fn update_message_bar(&mut self) -> Result<()> {
+    fn scroll_wrapped_lines(&mut self, rows: &Rope, cy: usize) {
+        let width = self.text_cols();
+        self.coloff = 0;
+
+        // Index of visual line where cursor is in the cursor row
+        let (line, col) = if cy < rows.len() {
+            let starts = wrap_starts(&rows[cy], width);
+            let idx = starts.iter().rposition(|s| *s <= self.rx).unwrap_or(0);
+            // Cursor at the end of full-width line stays at the last column
+            (
+                idx,
+                cmp::min(self.rx - starts[idx], width.saturating_sub(1)),
+            )
+        } else {
+            (0, 0)
+        };
+
+        // Scroll down until the visual line of cursor fits in screen. Note that rows above cursor
+        // are already in screen since every row has at least one line.
+        let mut height: usize = (self.rowoff..cy)
+            .map(|y| wrap_starts(&rows[y], width).len())
+            .sum::<usize>()
+            + line;
+        while height >= self.rows() && self.rowoff < cy {
+            height -= wrap_starts(&rows[self.rowoff], width).len();
+            self.rowoff += 1;
+        }
+
+        self.cursor_pos = (cmp::min(height, self.rows().saturating_sub(1)), col);
+    }
+
+    // Lines to render in screen from rowoff
+    fn screen_lines(&self, rows: &Rope) -> Vec<ScreenLine> {
+        let num_lines = self.rows();
+        let width = self.text_cols();
+        let mut lines = Vec::with_capacity(num_lines);
+        let mut file_row = self.rowoff;
+        while lines.len() < num_lines {
+            if !self.soft_wrap || file_row >= rows.len() {
+                lines.push(ScreenLine {
+                    file_row,
+                    start: self.coloff,
+                    end: self.coloff + width,
+                    head: true,
+                });
+            } else {
+                let starts = wrap_starts(&rows[file_row], width);
+                for (i, start) in starts.iter().enumerate() {
+                    lines.push(ScreenLine {
+                        file_row,
+                        start: *start,
+                        end: starts.get(i + 1).copied().unwrap_or(start + width),
+                        head: i == 0,
+                    });
+                }
+            }
+            file_row += 1;
+        }
+        lines.truncate(num_lines);
+        lines
+    }
+
+    // Move cursor to the visual line above or below in soft wrap mode. Returns None when the
+    // cursor is not moved within wrapped lines
+    pub fn next_visual_line(
+        &self,
+        rows: &Rope,
+        (cx, cy): (usize, usize),
+        dir: CursorDir,
+    ) -> Option<(usize, usize)> {
+        if !self.soft_wrap || cy >= rows.len() {
+            return None;
+        }
+
+        let width = self.text_cols();
+        let starts = wrap_starts(&rows[cy], width);
+        let rx = rows[cy].rx_from_cx(cx);
+        let idx = starts.iter().rposition(|s| *s <= rx).unwrap_or(0);
+        let col = rx - starts[idx];
+
+        // Find row and range of display columns of the destination line
+        let (y, start, end) = match dir {
+            CursorDir::Up if idx > 0 => (cy, starts[idx - 1], Some(starts[idx])),
+            CursorDir::Up if cy > 0 => {
+                let prev = wrap_starts(&rows[cy - 1], width);
+                (cy - 1, prev[prev.len() - 1], None)
+            }
+            CursorDir::Down if idx + 1 < starts.len() => {
+                (cy, starts[idx + 1], starts.get(idx + 2).copied())
+            }
+            CursorDir::Down if cy + 1 < rows.len() => {
+                let next = wrap_starts(&rows[cy + 1], width);
+                (cy + 1, 0, next.get(1).copied())
+            }
+            _ => return None,
+        };
+
+        // Keep display column in the line. Do not go beyond the end of the line
+        let rx = start + col;
+        let rx = match end {
+            Some(end) if rx >= end => end - 1,
+            _ => rx,
+        };
+        Some((rows[y].cx_from_rx(rx), y))
+    }
+
+    fn update_gutter(&mut self, num_lines: usize, cy: usize) {
+        let width = match self.line_numbers {
+            LineNumbers::Off => 0,
+            _ => num_lines.max(1).to_string().len() + 1, // Adapt to the largest line number
+        };
+        // Keep at least one column for text
+        let width = cmp::min(width, self.num_cols.saturating_sub(1));
+        if width != self.gutter_width {
+            self.gutter_width = width;
+            self.set_dirty_start(self.rowoff);
+        }
+
+        if self.gutter_width > 0 && cy != self.prev_cy {
+            match self.line_numbers {
+                LineNumbers::Relative => self.set_dirty_start(self.rowoff), // All numbers change
+                _ => self.set_dirty_start(cmp::min(cy, self.prev_cy)),
+            }
+        }
+        self.prev_cy = cy;
+    }
+
+    fn expire_message(&mut self) -> Result<()> {
         if let Some(m) = &self.message {
             if SystemTime::now().duration_since(m.timestamp)?.as_secs() > 5 {
                 self.unset_message();
             }
         }
-        if self.draw_message == DrawMessage::Close {
-            self.set_dirty_start(self.num_rows); // Closing message bar reveals one more line
+        Ok(())
+    }
+
+    fn update_message_bar(&mut self, rows: &Rope) -> Result<()> {
+        self.expire_message()?;
+        if self.draw_message == DrawMessage::Close && self.bottom {
+            // Closing message bar reveals one more line
+            let line = if self.soft_wrap {
+                self.screen_lines(rows)[self.num_rows].file_row
+            } else {
+                self.num_rows
+            };
+            self.set_dirty_start(line);
         }
         Ok(())
     }
@@ -488,21 +823,68 @@ impl<W: Write> Screen<W> {
         hl: &mut Highlighting,
         status_bar: &StatusBar,
     ) -> Result<()> {
+        self.update_gutter(buf.rows().len(), buf.cy());
         self.do_scroll(buf.rows(), buf.cursor());
-        self.update_message_bar()?; // This must be updated here since it affects area of highlighting
+        self.update_message_bar(buf.rows())?; // This must be updated here since it affects area of highlighting
         hl.update(buf.rows(), self.rowoff + self.rows());
         self.redraw(buf, hl, status_bar)?;
         self.after_render();
         Ok(())
     }
 
+    // Render a window which is not focused. Its output is flushed with rendering the focused
+    // window by `render`.
+    pub fn render_window(
+        &mut self,
+        id: usize,
+        buf: &TextBuffer,
+        cursor: (usize, usize),
+        hl: &mut Highlighting,
+        status_bar: &StatusBar,
+    ) -> Result<()> {
+        debug_assert_ne!(id, self.focus);
+        self.expire_message()?;
+
+        let focused = self.save_view();
+        let view = self.views[id].clone();
+        self.load_view(&view);
+
+        // Opening or closing message bar changes height of the window
+        let rows = self.rows();
+        if rows != view.drawn_rows {
+            self.set_dirty_start(self.rowoff);
+        }
+        self.update_gutter(buf.rows().len(), cursor.1);
+        self.do_scroll(buf.rows(), cursor);
+        hl.update(buf.rows(), self.rowoff + rows);
+
+        let mut out = mem::take(&mut self.pending);
+        if let Some(s) = self.dirty_start {
+            self.draw_rows(&mut out, s, buf.rows(), cursor.1, hl)?;
+        }
+        if view.redraw_status || status_bar.redraw || rows != view.drawn_rows {
+            self.draw_status_bar(&mut out, status_bar)?;
+        }
+        self.pending = out;
+        self.dirty_start = None;
+
+        self.views[id] = View {
+            drawn_rows: rows,
+            redraw_status: false,
+            ..self.save_view()
+        };
+        self.load_view(&focused);
+        Ok(())
+    }
+
     pub fn render_help(&mut self) -> Result<()> {
         let help: Vec<_> = HELP
             .split('\n')
             .skip_while(|s| !s.contains(':'))
             .map(str::trim_start)
             .collect();
-        let rows = self.rows();
+        // Help covers all windows except for message bar
+        let rows = self.term_rows - 1;
 
         let vertical_margin = if help.len() < rows {
             (rows - help.len()) / 2
@@ -510,13 +892,13 @@ impl<W: Write> Screen<W> {
             0
         };
         let help_max_width = help.iter().map(|l| l.len()).max().unwrap();
-        let left_margin = if help_max_width < self.num_cols {
-            (self.num_cols - help_max_width) / 2
+        let left_margin = if help_max_width < self.term_cols {
+            (self.term_cols - help_max_width) / 2
         } else {
             0
         };
 
-        let mut buf = Vec::with_capacity(rows * self.num_cols);
+        let mut buf = Vec::with_capacity(rows * self.term_cols);
 
         for y in 0..vertical_margin {
             write!(buf, "\x1b[{}H", y + 1)?;
@@ -530,7 +912,7 @@ impl<W: Write> Screen<W> {
             write!(buf, "\x1b[{}H", y + 1)?;
             buf.write(left_pad.as_bytes())?;
 
-            let help = &help[idx][..cmp::min(help[idx].len(), self.num_cols)];
+            let help = &help[idx][..cmp::min(help[idx].len(), self.term_cols)];
             buf.write(self.term_color.sequence(Color::Cyan))?;
             let mut cols = help.split(':');
             if let Some(col) = cols.next() {
@@ -574,13 +956,186 @@ impl<W: Write> Screen<W> {
             return Err(Error::TooSmallWindow(w, h));
         }
 
-        self.num_rows = h.saturating_sub(2);
-        self.num_cols = w;
-        self.dirty_start = Some(0);
+        self.term_cols = w;
+        self.term_rows = h;
+        self.layout_windows()?;
 
         Ok(true)
     }
 
+    fn save_view(&self) -> View {
+        View {
+            rx: self.rx,
+            rowoff: self.rowoff,
+            coloff: self.coloff,
+            dirty_start: self.dirty_start,
+            gutter_width: self.gutter_width,
+            prev_cy: self.prev_cy,
+            cursor_pos: self.cursor_pos,
+            top: self.top,
+            left: self.left,
+            num_cols: self.num_cols,
+            num_rows: self.num_rows,
+            bottom: self.bottom,
+            right_edge: self.right_edge,
+            drawn_rows: 0,
+            redraw_status: true,
+        }
+    }
+
+    fn load_view(&mut self, view: &View) {
+        self.rx = view.rx;
+        self.rowoff = view.rowoff;
+        self.coloff = view.coloff;
+        self.dirty_start = view.dirty_start;
+        self.gutter_width = view.gutter_width;
+        self.prev_cy = view.prev_cy;
+        self.cursor_pos = view.cursor_pos;
+        self.load_area(view);
+    }
+
+    fn load_area(&mut self, view: &View) {
+        self.top = view.top;
+        self.left = view.left;
+        self.num_cols = view.num_cols;
+        self.num_rows = view.num_rows;
+        self.bottom = view.bottom;
+        self.right_edge = view.right_edge;
+    }
+
+    // Calculate areas of windows from the layout and terminal size. All windows are redrawn
+    fn layout_windows(&mut self) -> Result<()> {
+        let area = Rect {
+            top: 0,
+            left: 0,
+            width: self.term_cols,
+            height: self.term_rows - 1, // Last line is for message bar
+        };
+        let mut rects = Vec::with_capacity(self.views.len());
+        self.layout.rects(area, &mut rects);
+
+        for (id, rect) in rects {
+            let right_edge = rect.left + rect.width == area.width;
+            let num_cols = if right_edge {
+                rect.width
+            } else {
+                rect.width - 1
+            };
+            if rect.height < 2 || num_cols < 1 {
+                return Err(Error::TooSmallWindow(self.term_cols, self.term_rows));
+            }
+            let view = &mut self.views[id];
+            view.top = rect.top;
+            view.left = rect.left;
+            view.num_cols = num_cols;
+            view.num_rows = rect.height - 1; // Status bar is at bottom of each window
+            view.bottom = rect.top + rect.height == area.height;
+            view.right_edge = right_edge;
+            view.dirty_start = Some(0);
+            view.redraw_status = true;
+        }
+
+        let view = self.views[self.focus].clone();
+        self.load_area(&view);
+        self.dirty_start = Some(0);
+        Ok(())
+    }
+
+    // Split the focused window. The new window shows the same area as the focused window. Returns
+    // ID of the new window or None when the window is too small to split
+    pub fn split_window(&mut self, dir: SplitDir) -> Result<Option<usize>> {
+        let width = if self.right_edge {
+            self.num_cols
+        } else {
+            self.num_cols + 1
+        };
+        let height = self.num_rows + 1;
+        let too_small = match dir {
+            SplitDir::Horizontal => height / 2 < 2,
+            SplitDir::Vertical => width / 2 < 2,
+        };
+        if too_small {
+            return Ok(None);
+        }
+
+        let id = self.views.len();
+        self.views.push(self.save_view());
+        self.layout.split(self.focus, id, dir);
+        self.layout_windows()?;
+        Ok(Some(id))
+    }
+
+    // Close the focused window and move focus to the previous window. IDs of windows after the
+    // closed one are shifted. Returns ID of the newly focused window or None when the window is
+    // the last one
+    pub fn close_window(&mut self) -> Result<Option<usize>> {
+        if self.views.len() == 1 {
+            return Ok(None);
+        }
+
+        let order = self.window_order();
+        let idx = order.iter().position(|id| *id == self.focus).unwrap();
+        let next = order[if idx == 0 { 1 } else { idx - 1 }];
+        let next = if next > self.focus { next - 1 } else { next };
+
+        self.layout.remove(self.focus);
+        self.views.remove(self.focus);
+        self.focus = next;
+        let view = self.views[next].clone();
+        self.load_view(&view);
+        self.layout_windows()?;
+        Ok(Some(next))
+    }
+
+    pub fn focus_window(&mut self, id: usize) {
+        if id == self.focus {
+            return;
+        }
+        self.views[self.focus] = self.save_view();
+        let view = self.views[id].clone();
+        self.load_view(&view);
+        self.focus = id;
+    }
+
+    // Window IDs ordered from top-left to bottom-right
+    fn window_order(&self) -> Vec<usize> {
+        let mut rects = Vec::with_capacity(self.views.len());
+        self.layout.rects(Rect::default(), &mut rects);
+        rects.into_iter().map(|(id, _)| id).collect()
+    }
+
+    pub fn next_window(&self) -> usize {
+        let order = self.window_order();
+        let idx = order.iter().position(|id| *id == self.focus).unwrap();
+        order[(idx + 1) % order.len()]
+    }
+
+    pub fn focused_window(&self) -> usize {
+        self.focus
+    }
+
+    pub fn window_count(&self) -> usize {
+        self.views.len()
+    }
+
+    pub fn set_window_dirty_start(&mut self, id: usize, start: usize) {
+        if id == self.focus {
+            self.set_dirty_start(start);
+            return;
+        }
+        let view = &mut self.views[id];
+        view.dirty_start = Some(view.dirty_start.map_or(start, |s| cmp::min(s, start)));
+    }
+
+    // Redraw all windows including their status bars
+    pub fn set_all_dirty(&mut self) {
+        self.set_dirty_start(self.rowoff);
+        for view in self.views.iter_mut() {
+            view.dirty_start = Some(view.rowoff);
+            view.redraw_status = true;
+        }
+    }
+
     fn set_message(&mut self, m: Option<StatusMessage>) {
         let op = match (&self.message, &m) {
             (Some(p), Some(n)) if p.text == n.text => DrawMessage::DoNothing,
@@ -607,7 +1162,7 @@ impl<W: Write> Screen<W> {
     }
 
     pub fn rows(&self) -> usize {
-        if self.message.is_some() {
+        if self.message.is_some() || !self.bottom {
             self.num_rows
         } else {
             self.num_rows + 1
@@ -618,10 +1173,47 @@ impl<W: Write> Screen<W> {
         self.num_cols
     }
 
+    // Number of columns to render text excluding line number gutter
+    fn text_cols(&self) -> usize {
+        self.num_cols - self.gutter_width
+    }
+
+    pub fn line_numbers(&self) -> LineNumbers {
+        self.line_numbers
+    }
+
+    pub fn soft_wrap(&self) -> bool {
+        self.soft_wrap
+    }
+
+    pub fn set_soft_wrap(&mut self, enabled: bool) {
+        self.soft_wrap = enabled;
+        self.coloff = 0;
+        for view in self.views.iter_mut() {
+            view.coloff = 0;
+        }
+        self.set_all_dirty();
+    }
+
+    pub fn set_line_numbers(&mut self, line_numbers: LineNumbers) {
+        self.line_numbers = line_numbers;
+        self.set_all_dirty();
+    }
+
+    // Line of message bar at bottom of terminal (1-based)
+    pub fn message_row(&self) -> usize {
+        self.term_rows
+    }
+
     pub fn message_text(&self) -> &'_ str {
         self.message.as_ref().map(|m| m.text.as_str()).unwrap_or("")
     }
 
+    pub fn write_clipboard(&mut self, text: &str) -> Result<()> {
+        let in_tmux = env::var_os("TMUX").is_some();
+        self.write_flush(clipboard::osc52_sequence(text, in_tmux).as_bytes())
+    }
+
     pub fn force_set_cursor(&mut self, row: usize, col: usize) -> Result<()> {
         write!(self.output, "\x1b[{};{}H", row, col)?;
         self.output.flush()?;
@@ -643,7 +1235,7 @@ impl<W: Write> Drop for Screen<W> {
         // > This control combines the effects of the 1 0 4 7 and 1 0 4 8  modes. Use this with
         // > terminfo-based applications rather than the 4 7  mode.
         // https://invisible-island.net/xterm/ctlseqs/ctlseqs.html
-        self.write_flush(b"\x1b[?1049l\x1b[H")
+        self.write_flush(b"\x1b[?1004l\x1b[?2004l\x1b[?1049l\x1b[H")
             .expect("Back to normal screen buffer");
     }
 }
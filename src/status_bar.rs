@@ -1,5 +1,6 @@
+use crate::encoding::FileEncoding;
 use crate::language::Language;
-use crate::text_buffer::TextBuffer;
+use crate::text_buffer::{LineEnding, TextBuffer};
 
 pub struct StatusBar {
     pub modified: bool,
@@ -7,6 +8,10 @@ pub struct StatusBar {
     pub lang: Language,
     pub buf_pos: (usize, usize),
     pub line_pos: (usize, usize),
+    pub line_ending: LineEnding,
+    pub final_newline: bool,
+    pub encoding: FileEncoding,
+    pub hex_view: bool,
     pub redraw: bool,
 }
 
@@ -35,6 +40,10 @@ impl StatusBar {
     setter!(set_filename, filename, &str, filename.to_string());
     setter!(set_lang, lang, Language);
     setter!(set_line_pos, line_pos, (usize, usize));
+    setter!(set_line_ending, line_ending, LineEnding);
+    setter!(set_final_newline, final_newline, bool);
+    setter!(set_encoding, encoding, FileEncoding);
+    setter!(set_hex_view, hex_view, bool);
 
     pub fn from_buffer(buf: &TextBuffer, buf_pos: (usize, usize)) -> Self {
         Self {
@@ -43,6 +52,10 @@ impl StatusBar {
             lang: buf.lang(),
             buf_pos,
             line_pos: (buf.cy() + 1, buf.rows().len()),
+            line_ending: buf.line_ending(),
+            final_newline: buf.final_newline(),
+            encoding: buf.encoding(),
+            hex_view: buf.is_read_only(),
             redraw: false,
         }
     }
@@ -53,13 +66,31 @@ impl StatusBar {
             self.filename,
             self.buf_pos.0,
             self.buf_pos.1,
-            if self.modified { "(modified) " } else { "" }
+            if self.hex_view {
+                "(read-only) "
+            } else if self.modified {
+                "(modified) "
+            } else {
+                ""
+            }
         )
     }
 
     pub fn right(&self) -> String {
         let (lang, (y, len)) = (self.lang, self.line_pos);
-        format!("{} {}/{}", lang.name(), y, len)
+        if self.hex_view {
+            return format!("hex {}/{}", y, len);
+        }
+        let noeol = if self.final_newline { "" } else { " noeol" };
+        format!(
+            "{} {} {}{} {}/{}",
+            lang.name(),
+            self.encoding,
+            self.line_ending.name(),
+            noeol,
+            y,
+            len
+        )
     }
 
     pub fn update_from_buf(&mut self, buf: &TextBuffer) {
@@ -67,5 +98,9 @@ impl StatusBar {
         self.set_lang(buf.lang());
         self.set_filename(buf.filename());
         self.set_line_pos((buf.cy() + 1, buf.rows().len()));
+        self.set_line_ending(buf.line_ending());
+        self.set_final_newline(buf.final_newline());
+        self.set_encoding(buf.encoding());
+        self.set_hex_view(buf.is_read_only());
     }
 }
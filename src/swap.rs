@@ -0,0 +1,112 @@
+use crate::save;
+use std::collections::HashMap;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Read};
+use std::os::unix::fs::OpenOptionsExt;
+use std::path::{Path, PathBuf};
+use std::process;
+
+// First line of swap file records which process on which host is editing the file
+const HEADER: &str = "kiro-swp";
+
+// Swap file keeps unsaved text of buffer so that it can be recovered after crash or disconnection
+// of SSH session. It is put in the same directory as the file as hidden file
+pub fn path_of(file: &Path) -> PathBuf {
+    let name = file
+        .file_name()
+        .map(|n| n.to_string_lossy())
+        .unwrap_or_default();
+    file.with_file_name(format!(".{}.kiro-swp", name))
+}
+
+fn host_name() -> String {
+    let mut buf = [0u8; 256];
+    if unsafe { libc::gethostname(buf.as_mut_ptr() as *mut libc::c_char, buf.len()) } == -1 {
+        return String::new();
+    }
+    let len = buf.iter().position(|&b| b == 0).unwrap_or(buf.len());
+    String::from_utf8_lossy(&buf[..len]).into_owned()
+}
+
+fn is_running(pid: u32) -> bool {
+    // Signal 0 only checks the process exists. EPERM means it is owned by another user
+    let ret = unsafe { libc::kill(pid as libc::pid_t, 0) };
+    ret == 0 || io::Error::last_os_error().raw_os_error() == Some(libc::EPERM)
+}
+
+pub struct Swap {
+    pub text: String,
+    pid: u32,
+    host: String,
+}
+
+impl Swap {
+    // Another kiro process running on this machine is editing the file. The swap file is not
+    // leftover of crashed session so it must not be touched
+    pub fn owner(&self) -> Option<u32> {
+        if self.pid != process::id() && self.host == host_name() && is_running(self.pid) {
+            Some(self.pid)
+        } else {
+            None
+        }
+    }
+}
+
+// Text in swap file is always UTF-8 and each line ends with LF. Other users cannot read it since
+// unsaved text of a private file may be sensitive
+pub fn write(file: &Path, text: &str) -> io::Result<()> {
+    let header = format!("{} {} {}\n", HEADER, process::id(), host_name());
+    save::replace_private_file(&path_of(file), (header + text).as_bytes())
+}
+
+// Read swap file left by previous session or written by other process. Returns None when no valid
+// swap file exists. Symlink is not followed not to show content of an arbitrary file
+pub fn read(file: &Path) -> Option<Swap> {
+    let mut f = OpenOptions::new()
+        .read(true)
+        .custom_flags(libc::O_NOFOLLOW)
+        .open(path_of(file))
+        .ok()?;
+    if !f.metadata().ok()?.is_file() {
+        return None;
+    }
+    let mut content = String::new();
+    f.read_to_string(&mut content).ok()?;
+
+    let (header, text) = content.split_once('\n')?;
+    let mut fields = header.splitn(3, ' ');
+    if fields.next()? != HEADER {
+        return None;
+    }
+    let pid = fields.next()?.parse().ok()?;
+    let host = fields.next().unwrap_or("").to_string();
+    Some(Swap {
+        text: text.to_string(),
+        pid,
+        host,
+    })
+}
+
+pub fn remove(file: &Path) {
+    let _ = fs::remove_file(path_of(file)); // Swap file may not exist
+}
+
+// Summary of line-wise difference between the text of file and the text in swap file. Returns
+// the numbers of added and removed lines. Order of lines is ignored not to take long time on
+// large files
+pub fn diff_summary<'a, I: Iterator<Item = &'a str>>(lines: I, swap: &str) -> (usize, usize) {
+    let mut counts = HashMap::new();
+    for line in swap.lines() {
+        *counts.entry(line).or_insert(0isize) += 1;
+    }
+    for line in lines {
+        *counts.entry(line).or_insert(0isize) -= 1;
+    }
+    counts.values().fold((0, 0), |(added, removed), &c| {
+        if c > 0 {
+            (added + c as usize, removed)
+        } else {
+            (added, removed + (-c) as usize)
+        }
+    })
+}
@@ -1,13 +1,21 @@
 use crate::edit_diff::{EditDiff, UndoRedo};
+use crate::encoding::FileEncoding;
 use crate::error::Result;
+use crate::hex_dump;
 use crate::history::History;
 use crate::language::{Indent, Language};
+use crate::rope::{self, Rope};
 use crate::row::Row;
+use crate::save::{self, Backup, FileStamp};
+use crate::swap;
+use std::cell::RefCell;
 use std::cmp;
-use std::fs::File;
-use std::io::{self, BufRead, Write};
+use std::fs;
+use std::io::{self, Read};
+use std::iter;
+use std::mem;
 use std::path::{Path, PathBuf};
-use std::slice;
+use std::rc::Rc;
 
 // Contain both actual path sequence and display string
 pub struct FilePath {
@@ -41,7 +49,7 @@ pub enum CursorDir {
     Down,
 }
 
-pub struct Lines<'a>(slice::Iter<'a, Row>);
+pub struct Lines<'a>(rope::Iter<'a>);
 
 impl<'a> Iterator for Lines<'a> {
     type Item = &'a str;
@@ -51,13 +59,46 @@ impl<'a> Iterator for Lines<'a> {
     }
 
     fn size_hint(&self) -> (usize, Option<usize>) {
-        let len = self.0.as_slice().len();
-        (len, Some(len))
+        self.0.size_hint()
     }
 }
 
 impl<'a> ExactSizeIterator for Lines<'a> {}
 
+// Line ending style of file. It is detected on opening the file and preserved on saving it
+#[derive(Clone, Copy, PartialEq, Debug)]
+pub enum LineEnding {
+    Lf,
+    CrLf,
+}
+
+impl LineEnding {
+    // CRLF is detected only when every newline is preceded by '\r'. Otherwise '\r' is kept as a
+    // part of line so that saving the file does not change any byte
+    fn detect(text: &str) -> Self {
+        let lf = text.bytes().filter(|&b| b == b'\n').count();
+        if lf > 0 && text.matches("\r\n").count() == lf {
+            LineEnding::CrLf
+        } else {
+            LineEnding::Lf
+        }
+    }
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "\n",
+            LineEnding::CrLf => "\r\n",
+        }
+    }
+
+    pub fn name(self) -> &'static str {
+        match self {
+            LineEnding::Lf => "LF",
+            LineEnding::CrLf => "CRLF",
+        }
+    }
+}
+
 pub struct TextBuffer {
     // (x, y) coordinate in internal text buffer of rows
     cx: usize,
@@ -65,7 +106,7 @@ pub struct TextBuffer {
     // File editor is opening
     file: Option<FilePath>,
     // Lines of text buffer
-    row: Vec<Row>,
+    row: Rope,
     // Count how many times undo points are created in the buffer. This value is set to 0 at just
     // after loading the buffer. When saving the buffer to file, count is reset to 0.
     // When redo/undo is applied without ongoing changes, this count is +1/-1.
@@ -83,6 +124,33 @@ pub struct TextBuffer {
     // Flag to require screen update
     // TODO: Merge with Screen's dirty_start field by using RenderContext struct
     dirty_start: Option<usize>,
+    // (x, y) coordinate of mark. Region between mark and cursor is selected while mark is set
+    mark: Option<(usize, usize)>,
+    // Whole text of buffer. It is built on demand and kept until the next modification not to
+    // copy the whole text on every search
+    whole_text: RefCell<Option<Rc<String>>>,
+    // Line ending written after each line on saving
+    line_ending: LineEnding,
+    // False when the file does not end with newline. The last line is saved without newline
+    final_newline: bool,
+    // Encoding of the file. Text is saved in the same encoding as loaded
+    encoding: FileEncoding,
+    // Original bytes of binary file shown in hex view. The buffer is read-only while this is set
+    binary: Option<Rc<Vec<u8>>>,
+    // True when edit was rejected since the buffer is read-only
+    edit_rejected: bool,
+    // Metadata of the file when it was loaded or saved last
+    stamp: Option<FileStamp>,
+    // Text in swap file left by previous session which crashed. User chooses recovering it
+    leftover_swap: Option<String>,
+    // Process ID of another kiro editing the same file. Its swap file is neither read nor written
+    swap_owner: Option<u32>,
+    // True while the swap file is owned by this session. Only such swap file is removed on
+    // saving or quitting
+    swap_written: bool,
+    // True after the file was saved once in this session. The original file is backed up only
+    // on the first save
+    saved_once: bool,
 }
 
 impl TextBuffer {
@@ -91,13 +159,25 @@ impl TextBuffer {
             cx: 0,
             cy: 0,
             file: None,
-            row: vec![Row::empty()], // Ensure that every text ends with newline
+            row: Rope::from_lines(iter::once("")), // Ensure that every text ends with newline
             undo_count: 0,
             modified: false,
             lang: Language::Plain,
             history: History::default(),
             inserted_undo: false,
             dirty_start: Some(0), // Ensure to render first screen
+            mark: None,
+            whole_text: RefCell::new(None),
+            line_ending: LineEnding::Lf,
+            final_newline: true,
+            encoding: FileEncoding::default(),
+            binary: None,
+            edit_rejected: false,
+            stamp: None,
+            leftover_swap: None,
+            swap_owner: None,
+            swap_written: false,
+            saved_once: false,
         }
     }
 
@@ -106,46 +186,190 @@ impl TextBuffer {
             cx: 0,
             cy: 0,
             file: None,
-            row: lines.map(|s| Row::new(s.as_ref())).collect::<Result<_>>()?,
+            row: Rope::from_lines(lines),
             undo_count: 0,
             modified: false,
             lang: Language::Plain,
             history: History::default(),
             inserted_undo: false,
             dirty_start: Some(0), // Ensure to render first screen
+            mark: None,
+            whole_text: RefCell::new(None),
+            line_ending: LineEnding::Lf,
+            final_newline: true,
+            encoding: FileEncoding::default(),
+            binary: None,
+            edit_rejected: false,
+            stamp: None,
+            leftover_swap: None,
+            swap_owner: None,
+            swap_written: false,
+            saved_once: false,
         })
     }
 
+    // Create unnamed buffer from text read from the reader such as piped stdin
+    pub fn from_reader<R: Read>(mut reader: R) -> Result<Self> {
+        let mut bytes = vec![];
+        reader.read_to_end(&mut bytes)?;
+        let mut buf = Self::empty();
+        if !bytes.is_empty() {
+            buf.load(bytes);
+        }
+        Ok(buf)
+    }
+
     pub fn open<P: AsRef<Path>>(path: P) -> Result<Self> {
         let path = path.as_ref();
-        let file = Some(FilePath::from(path));
-        if !path.exists() {
-            // When the path does not exist, consider it as a new file
-            let mut buf = Self::empty();
-            buf.file = file;
-            buf.undo_count = 0;
-            buf.modified = false;
-            buf.lang = Language::detect(path);
-            return Ok(buf);
-        }
-
-        let row = io::BufReader::new(File::open(path)?)
-            .lines()
-            .map(|r| Row::new(r?))
-            .collect::<Result<_>>()?;
+        let mut buf = Self::empty();
+        buf.file = Some(FilePath::from(path));
+        buf.lang = Language::detect(path);
+        // When the path does not exist, consider it as a new file
+        if path.exists() {
+            // Record metadata before reading so that modification while reading is detected later
+            buf.stamp = FileStamp::of(path);
+            buf.load(fs::read(path)?); // Read whole file at once
+        }
+        if buf.binary.is_none() {
+            match swap::read(path) {
+                Some(swap) if swap.owner().is_some() => buf.swap_owner = swap.owner(),
+                // Swap file which has the same text as the file is no longer necessary
+                Some(swap) if swap.text != *buf.text() => buf.leftover_swap = Some(swap.text),
+                _ => swap::remove(path),
+            }
+        }
+        Ok(buf)
+    }
 
-        Ok(Self {
-            cx: 0,
-            cy: 0,
-            file,
-            row,
-            undo_count: 0,
-            modified: false,
-            lang: Language::detect(path),
-            history: History::default(),
-            inserted_undo: false,
-            dirty_start: Some(0),
-        })
+    // Load the file again discarding all changes and undo history. Cursor stays at the same
+    // position as possible
+    pub fn reload(&mut self) -> Result<()> {
+        self.remove_swap(); // Changes in swap file are also discarded
+        let file = if let Some(file) = self.file.take() {
+            file
+        } else {
+            return Ok(());
+        };
+        let mut buf = match Self::open(&file.path) {
+            Ok(buf) => buf,
+            Err(err) => {
+                self.file = Some(file);
+                return Err(err);
+            }
+        };
+        if buf.binary.is_none() {
+            buf.lang = self.lang; // Keep language set by user
+        }
+        buf.file = Some(file);
+        buf.saved_once = self.saved_once; // Do not back up the file again in this session
+        buf.set_cursor_line_col(self.cy + 1, self.cx + 1);
+        *self = buf;
+        Ok(())
+    }
+
+    // True when the file was modified by other program after it was loaded or saved last
+    pub fn changed_on_disk(&self) -> bool {
+        match (&self.file, &self.stamp) {
+            (Some(file), Some(stamp)) => {
+                FileStamp::of(&file.path).is_some_and(|current| current != *stamp)
+            }
+            _ => false,
+        }
+    }
+
+    // Write the current text to swap file to recover unsaved changes after crash. Swap file left
+    // by previous session is not overwritten until user answers whether to recover it
+    pub fn write_swap(&mut self) -> io::Result<()> {
+        if self.leftover_swap.is_some() || self.swap_owner.is_some() || self.binary.is_some() {
+            return Ok(());
+        }
+        if let Some(file) = &self.file {
+            swap::write(&file.path, &self.text())?;
+            self.swap_written = true;
+        }
+        Ok(())
+    }
+
+    pub fn swap_written(&self) -> bool {
+        self.swap_written
+    }
+
+    // Remove swap file owned by this session. Leftover swap file is kept while it is not answered
+    pub fn remove_swap(&mut self) {
+        if !mem::take(&mut self.swap_written) {
+            return;
+        }
+        if let Some(file) = &self.file {
+            swap::remove(&file.path);
+        }
+    }
+
+    pub fn swap_owner(&self) -> Option<u32> {
+        self.swap_owner
+    }
+
+    // Numbers of added and removed lines in swap file left by previous session
+    pub fn leftover_swap_summary(&self) -> Option<(usize, usize)> {
+        let text = self.leftover_swap.as_ref()?;
+        Some(swap::diff_summary(self.lines(), text))
+    }
+
+    // Replace the whole text with the text in leftover swap file as one undo entry
+    pub fn recover_swap(&mut self) {
+        let text = if let Some(text) = self.leftover_swap.take() {
+            text
+        } else {
+            return;
+        };
+        self.swap_written = true; // Recovered text is now owned by this session
+        let text = text.strip_suffix('\n').unwrap_or(&text);
+        let (x, y) = self.cursor();
+        if let Some(last) = self.row.last() {
+            let end = (last.len(), self.row.len() - 1);
+            self.replace_range((0, 0), end, text);
+        } else {
+            self.insert_str(text);
+        }
+        self.set_cursor_line_col(y + 1, x + 1);
+    }
+
+    // Delete leftover swap file without recovering it
+    pub fn discard_swap(&mut self) {
+        if self.leftover_swap.take().is_some() {
+            if let Some(file) = &self.file {
+                swap::remove(&file.path);
+            }
+        }
+    }
+
+    // Load the bytes as content of the buffer. Binary content is shown in read-only hex view
+    fn load(&mut self, bytes: Vec<u8>) {
+        if hex_dump::is_binary(&bytes) {
+            self.row = Rope::from_text(&hex_dump::dump(&bytes), false);
+            self.lang = Language::Plain;
+            self.binary = Some(Rc::new(bytes));
+            return;
+        }
+
+        let (text, encoding) = FileEncoding::decode(bytes);
+        self.line_ending = LineEnding::detect(&text);
+        self.row = Rope::from_text(&text, self.line_ending == LineEnding::CrLf);
+        self.final_newline = text.is_empty() || text.ends_with('\n');
+        self.encoding = encoding;
+    }
+
+    // Edit methods do nothing on read-only buffer. Rejected edit is notified to user later
+    fn is_writable(&mut self) -> bool {
+        if self.binary.is_some() {
+            self.edit_rejected = true;
+            return false;
+        }
+        true
+    }
+
+    // Returns true when some edit was rejected since the buffer is read-only
+    pub fn take_edit_rejected(&mut self) -> bool {
+        mem::take(&mut self.edit_rejected)
     }
 
     fn set_dirty_start(&mut self, line: usize) {
@@ -158,27 +382,35 @@ impl TextBuffer {
     }
 
     fn apply_diff(&mut self, diff: &EditDiff, which: UndoRedo) {
-        let (x, y) = diff.apply(&mut self.row, which);
+        let (x, y) = diff.apply(&mut self.row, &mut self.line_ending, which);
+        *self.whole_text.get_mut() = None;
         self.set_cursor(x, y);
         self.set_dirty_start(y);
     }
 
     fn new_diff(&mut self, diff: EditDiff) {
         self.apply_diff(&diff, UndoRedo::Redo);
+        self.mark = None; // Modifying text deactivates mark
         self.modified = true;
         self.history.push(diff); // Remember diff for undo/redo
     }
 
     fn insert_undo_point(&mut self) {
         if !self.inserted_undo {
-            if self.history.finish_ongoing_edit() {
-                self.undo_count = self.undo_count.saturating_add(1);
-            }
-            self.modified = false;
+            self.close_undo_point();
             self.inserted_undo = true;
         }
     }
 
+    // Finish the ongoing change as one undo entry immediately. Succeeding changes in the same key
+    // input (e.g. insert_char) are not merged into the entry
+    pub fn close_undo_point(&mut self) {
+        if self.history.finish_ongoing_edit() {
+            self.undo_count = self.undo_count.saturating_add(1);
+        }
+        self.modified = false;
+    }
+
     // This method must be called after handling one key input.
     // TODO: This should be replaced with Drop when separating logic to edit text buffer from TextBuffer
     // by introducing RenderContext.
@@ -190,6 +422,9 @@ impl TextBuffer {
     }
 
     pub fn insert_char(&mut self, ch: char) {
+        if !self.is_writable() {
+            return;
+        }
         // Don't add undo point to squash multiple insert_char changes into one undo
         if self.cy == self.row.len() {
             self.new_diff(EditDiff::Newline);
@@ -198,6 +433,9 @@ impl TextBuffer {
     }
 
     pub fn insert_tab(&mut self) {
+        if !self.is_writable() {
+            return;
+        }
         self.insert_undo_point();
         match self.lang.indent() {
             Indent::AsIs => self.insert_char('\t'),
@@ -207,22 +445,27 @@ impl TextBuffer {
         }
     }
 
-    fn concat_next_line(&mut self) {
+    // Returns removed text, which is always a newline
+    fn concat_next_line(&mut self) -> String {
         // TODO: Move buffer rather than copy
         let removed = self.row[self.cy + 1].buffer().to_owned();
         self.new_diff(EditDiff::DeleteLine(self.cy + 1, removed.clone()));
         self.new_diff(EditDiff::Append(self.cy, removed));
+        "\n".to_string()
     }
 
-    fn squash_to_previous_line(&mut self) {
+    fn squash_to_previous_line(&mut self) -> String {
         // Move cursor to previous line
         self.cy -= 1;
         // At top of line, backspace concats current line to previous line
         self.cx = self.row[self.cy].len(); // Move cursor column to end of previous line
-        self.concat_next_line();
+        self.concat_next_line()
     }
 
     pub fn delete_char(&mut self) {
+        if !self.is_writable() {
+            return;
+        }
         if self.cy == self.row.len() || self.cx == 0 && self.cy == 0 {
             return;
         }
@@ -236,40 +479,55 @@ impl TextBuffer {
         }
     }
 
-    pub fn delete_until_end_of_line(&mut self) {
+    // Deletion methods below return deleted text. None means nothing was deleted
+
+    pub fn delete_until_end_of_line(&mut self) -> Option<String> {
+        if !self.is_writable() {
+            return None;
+        }
         if self.cy == self.row.len() {
-            return;
+            return None;
         }
         self.insert_undo_point();
         let row = &self.row[self.cy];
         if self.cx == row.len() {
             // Do nothing when cursor is at end of line of end of text buffer
             if self.cy == self.row.len() - 1 {
-                return;
+                return None;
             }
-            self.concat_next_line();
+            Some(self.concat_next_line())
         } else if self.cx < row.buffer().len() {
             let truncated = row[self.cx..].to_owned();
-            self.new_diff(EditDiff::Truncate(self.cy, truncated));
+            self.new_diff(EditDiff::Truncate(self.cy, truncated.clone()));
+            Some(truncated)
+        } else {
+            None
         }
     }
 
-    pub fn delete_until_head_of_line(&mut self) {
+    pub fn delete_until_head_of_line(&mut self) -> Option<String> {
+        if !self.is_writable() {
+            return None;
+        }
         if self.cx == 0 && self.cy == 0 || self.cy == self.row.len() {
-            return;
+            return None;
         }
         self.insert_undo_point();
         if self.cx == 0 {
-            self.squash_to_previous_line();
+            Some(self.squash_to_previous_line())
         } else {
             let removed = self.row[self.cy][..self.cx].to_owned();
-            self.new_diff(EditDiff::Remove(self.cx, self.cy, removed));
+            self.new_diff(EditDiff::Remove(self.cx, self.cy, removed.clone()));
+            Some(removed)
         }
     }
 
-    pub fn delete_word(&mut self) {
+    pub fn delete_word(&mut self) -> Option<String> {
+        if !self.is_writable() {
+            return None;
+        }
         if self.cx == 0 || self.cy == self.row.len() {
-            return;
+            return None;
         }
         self.insert_undo_point();
 
@@ -284,10 +542,199 @@ impl TextBuffer {
         }
 
         let removed = self.row[self.cy][x..self.cx].to_owned();
-        self.new_diff(EditDiff::Remove(self.cx, self.cy, removed));
+        self.new_diff(EditDiff::Remove(self.cx, self.cy, removed.clone()));
+        Some(removed)
+    }
+
+    // Remove text in range [start, end) and return the removed text. Lines are joined with '\n'
+    fn delete_range(&mut self, (sx, sy): (usize, usize), (ex, ey): (usize, usize)) -> String {
+        let removed = self.text_in_range((sx, sy), (ex, ey));
+        if sy == ey {
+            if !removed.is_empty() {
+                self.new_diff(EditDiff::Remove(ex, sy, removed.clone()));
+            }
+            return removed;
+        }
+
+        let head = self.row[sy][sx..].to_owned();
+        let tail = self.row[ey][ex..].to_owned();
+
+        if !head.is_empty() {
+            self.new_diff(EditDiff::Truncate(sy, head));
+        }
+        // Remove lines from bottom to keep line indices of diffs valid
+        for y in (sy + 1..=ey).rev() {
+            let line = self.row[y].buffer().to_owned();
+            self.new_diff(EditDiff::DeleteLine(y, line));
+        }
+        if !tail.is_empty() {
+            self.new_diff(EditDiff::Append(sy, tail));
+        }
+
+        self.set_cursor(sx, sy);
+        removed
+    }
+
+    // Insert text which may contain newlines at cursor without creating undo point
+    fn insert_text(&mut self, text: &str) {
+        if self.cy == self.row.len() {
+            self.new_diff(EditDiff::Newline);
+        }
+
+        let mut lines = text.split('\n');
+        let first = lines.next().unwrap();
+        let rest: Vec<_> = lines.collect();
+
+        if rest.is_empty() {
+            if !first.is_empty() {
+                self.new_diff(EditDiff::Insert(self.cx, self.cy, first.to_owned()));
+            }
+            return;
+        }
+
+        let (x, y) = (self.cx, self.cy);
+        let tail = self.row[y][x..].to_owned();
+        if !tail.is_empty() {
+            self.new_diff(EditDiff::Truncate(y, tail.clone()));
+        }
+        if !first.is_empty() {
+            self.new_diff(EditDiff::Append(y, first.to_owned()));
+        }
+        for (i, line) in rest.iter().enumerate() {
+            let mut line = line.to_string();
+            if i == rest.len() - 1 {
+                line.push_str(&tail); // Text after cursor follows the last inserted line
+            }
+            self.new_diff(EditDiff::InsertLine(y + i + 1, line));
+        }
+
+        let last_len = rest[rest.len() - 1].chars().count();
+        self.set_cursor(last_len, y + rest.len());
+    }
+
+    // Insert text at cursor as one undo entry. Cursor moves to the end of the inserted text
+    pub fn insert_str(&mut self, text: &str) {
+        if !self.is_writable() {
+            return;
+        }
+        self.insert_undo_point();
+        self.insert_text(text);
+        self.close_undo_point();
+    }
+
+    // Replace text in range [start, end) with the text as one undo entry
+    pub fn replace_range(&mut self, start: (usize, usize), end: (usize, usize), text: &str) {
+        if !self.is_writable() {
+            return;
+        }
+        self.insert_undo_point();
+        self.delete_range(start, end);
+        self.insert_text(text);
+        self.close_undo_point();
+    }
+
+    // Replace text in range [start, end) without finishing the undo entry. Multiple replacements
+    // are merged into one undo entry until close_undo_point() is called
+    pub fn replace_range_merged(&mut self, start: (usize, usize), end: (usize, usize), text: &str) {
+        if !self.is_writable() {
+            return;
+        }
+        self.insert_undo_point();
+        self.delete_range(start, end);
+        self.insert_text(text);
+    }
+
+    // Convert line endings of the whole buffer as one undo entry
+    pub fn set_line_ending(&mut self, line_ending: LineEnding) {
+        if !self.is_writable() {
+            return;
+        }
+        if self.line_ending == line_ending {
+            return;
+        }
+        self.insert_undo_point();
+        let (x, y) = self.cursor();
+        // Lines of mixed line endings keep trailing CR. Remove it not to save CR twice
+        let cr_lines: Vec<_> = (0..self.row.len())
+            .filter(|&y| self.row[y].buffer().ends_with('\r'))
+            .collect();
+        for cr_y in cr_lines {
+            self.new_diff(EditDiff::Truncate(cr_y, "\r".to_string()));
+        }
+        let x = self.row.get(y).map_or(x, |row| cmp::min(x, row.len()));
+        self.new_diff(EditDiff::LineEnding(x, y, self.line_ending, line_ending));
+        self.close_undo_point();
+    }
+
+    // Find a match from the position toward the end of buffer. `find` searches a line from the
+    // byte offset and returns the byte range of the match. Returns the range of the match
+    pub fn find_forward<F>(
+        &self,
+        (x, y): (usize, usize),
+        find: F,
+    ) -> Option<((usize, usize), (usize, usize))>
+    where
+        F: Fn(&str, usize) -> Option<(usize, usize)>,
+    {
+        for (cy, row) in self.row.iter().enumerate().skip(y) {
+            let start = if cy == y { row.byte_idx_of(x) } else { 0 };
+            if let Some((s, e)) = find(row.buffer(), start) {
+                return Some(((row.char_idx_of(s), cy), (row.char_idx_of(e), cy)));
+            }
+        }
+        None
+    }
+
+    pub fn delete_region(&mut self) -> Option<String> {
+        if !self.is_writable() {
+            return None;
+        }
+        let (start, end) = self.region()?;
+        self.insert_undo_point();
+        let removed = self.delete_range(start, end);
+        self.mark = None;
+        Some(removed)
+    }
+
+    pub fn indent_region(&mut self) {
+        if !self.is_writable() {
+            return;
+        }
+        let ((_, sy), (ex, ey)) = if let Some(region) = self.region() {
+            region
+        } else {
+            return;
+        };
+        self.insert_undo_point();
+
+        let indent = match self.lang.indent() {
+            Indent::AsIs => "\t",
+            Indent::Fixed(indent) => indent,
+        };
+        // When region ends at head of line, the line is not included in the region
+        let ey = if ex == 0 && sy < ey { ey - 1 } else { ey };
+        let (cx, cy) = self.cursor();
+        let mut indented_cursor_line = false;
+
+        for y in sy..=ey {
+            if self.row[y].buffer().is_empty() {
+                continue; // Do not indent empty lines
+            }
+            self.new_diff(EditDiff::Insert(0, y, indent.to_owned()));
+            indented_cursor_line |= y == cy;
+        }
+
+        if indented_cursor_line {
+            self.set_cursor(cx + indent.chars().count(), cy);
+        } else {
+            self.set_cursor(cx, cy);
+        }
     }
 
     pub fn delete_right_char(&mut self) {
+        if !self.is_writable() {
+            return;
+        }
         if self.cy == self.row.len()
             || self.cy == self.row.len() - 1 && self.cx == self.row[self.cy].len()
         {
@@ -299,6 +746,9 @@ impl TextBuffer {
     }
 
     pub fn insert_line(&mut self) {
+        if !self.is_writable() {
+            return;
+        }
         self.insert_undo_point();
         if self.cy >= self.row.len() {
             self.new_diff(EditDiff::Newline);
@@ -388,7 +838,7 @@ impl TextBuffer {
         }
 
         impl CharKind {
-            fn new_at(rows: &[Row], x: usize, y: usize) -> Self {
+            fn new_at(rows: &Rope, x: usize, y: usize) -> Self {
                 rows.get(y)
                     .and_then(|r| r.char_at_checked(x))
                     .map(|c| {
@@ -453,7 +903,7 @@ impl TextBuffer {
         }
     }
 
-    pub fn rows(&self) -> &[Row] {
+    pub fn rows(&self) -> &Rope {
         &self.row
     }
 
@@ -480,10 +930,43 @@ impl TextBuffer {
         self.cy
     }
 
+    pub fn line_ending(&self) -> LineEnding {
+        self.line_ending
+    }
+
+    pub fn final_newline(&self) -> bool {
+        self.final_newline
+    }
+
+    pub fn encoding(&self) -> FileEncoding {
+        self.encoding
+    }
+
+    // Original bytes when the buffer is in hex view
+    pub fn binary(&self) -> Option<Rc<Vec<u8>>> {
+        self.binary.clone()
+    }
+
+    pub fn is_read_only(&self) -> bool {
+        self.binary.is_some()
+    }
+
     pub fn lines(&self) -> Lines<'_> {
         Lines(self.row.iter())
     }
 
+    // Whole text of the buffer. Each line ends with newline. The text is copied from rows only
+    // when the buffer was modified since the last call
+    pub fn text(&self) -> Rc<String> {
+        let mut whole_text = self.whole_text.borrow_mut();
+        if let Some(text) = &*whole_text {
+            return Rc::clone(text);
+        }
+        let text = Rc::new(self.row.concat());
+        *whole_text = Some(Rc::clone(&text));
+        text
+    }
+
     pub fn set_file<S: Into<String>>(&mut self, file_path: S) {
         let file = FilePath::from_string(file_path);
         self.lang = Language::detect(&file.path);
@@ -498,7 +981,7 @@ impl TextBuffer {
         self.lang = lang;
     }
 
-    pub fn save(&mut self) -> std::result::Result<String, String> {
+    pub fn save(&mut self, backup: &Backup) -> std::result::Result<String, String> {
         self.insert_undo_point();
 
         let file = if let Some(file) = &self.file {
@@ -506,24 +989,50 @@ impl TextBuffer {
         } else {
             return Ok("".to_string()); // Canceled
         };
+        if self.binary.is_some() {
+            return Err("Could not save: Binary file is opened in read-only hex view".to_string());
+        }
+
+        // Encode the whole text before writing not to break the file on failure
+        // Newline can be encoded in any encoding
+        let eol = self.encoding.encode(self.line_ending.as_str()).unwrap();
+        let last = self.row.len().saturating_sub(1);
+        let mut bytes = self.encoding.bom().to_vec();
+        for (y, line) in self.row.iter().enumerate() {
+            let encoded = self.encoding.encode(line.buffer()).ok_or_else(|| {
+                format!(
+                    "Could not save: Line {} cannot be encoded in {}",
+                    y + 1,
+                    self.encoding,
+                )
+            })?;
+            bytes.extend_from_slice(&encoded);
+            // Keep the last line without newline as it was loaded
+            if y < last || self.final_newline {
+                bytes.extend_from_slice(&eol);
+            }
+        }
 
-        let f = match File::create(&file.path) {
-            Ok(f) => f,
+        if !self.saved_once {
+            if let Err(e) = save::backup(&file.path, backup) {
+                return Err(format!("Could not save: Backup failed: {}", e));
+            }
+        }
+        let warning = match save::write_file(&file.path, &bytes) {
+            Ok(warning) => warning,
             Err(e) => return Err(format!("Could not save: {}", e)),
         };
-        let mut f = io::BufWriter::new(f);
-        let mut bytes = 0;
-        for line in self.row.iter() {
-            let b = line.buffer();
-            writeln!(f, "{}", b).map_err(|e| format!("Could not write to file: {}", e))?;
-            bytes += b.as_bytes().len() + 1;
-        }
-        f.flush()
-            .map_err(|e| format!("Could not flush to file: {}", e))?;
 
         self.undo_count = 0;
         self.modified = false;
-        Ok(format!("{} bytes written to {}", bytes, &file.display))
+        self.stamp = FileStamp::of(&file.path);
+        self.saved_once = true;
+        let mut msg = format!("{} bytes written to {}", bytes.len(), file.display);
+        if let Some(e) = warning {
+            msg += &format!(" (could not flush directory: {})", e);
+        }
+        self.remove_swap();
+        Ok(msg)
     }
 
     pub fn set_cursor(&mut self, x: usize, y: usize) {
@@ -531,13 +1040,89 @@ impl TextBuffer {
         self.cy = y;
     }
 
+    // Move cursor to 1-based line and column. The position is clamped into the buffer
+    pub fn set_cursor_line_col(&mut self, line: usize, col: usize) {
+        if self.row.is_empty() {
+            return;
+        }
+        let y = cmp::min(line.saturating_sub(1), self.row.len() - 1);
+        let x = cmp::min(col.saturating_sub(1), self.row[y].len());
+        self.set_cursor(x, y);
+    }
+
     pub fn cursor(&self) -> (usize, usize) {
         (self.cx, self.cy)
     }
 
+    // Set mark at cursor. When mark is already set at cursor, it is deactivated (like Emacs's
+    // C-SPC C-SPC). Returns whether mark is active after this call
+    pub fn toggle_mark(&mut self) -> bool {
+        let cursor = self.cursor();
+        if self.mark == Some(cursor) {
+            self.mark = None;
+        } else {
+            self.mark = Some(cursor);
+        }
+        self.mark.is_some()
+    }
+
+    pub fn clear_mark(&mut self) {
+        self.mark = None;
+    }
+
+    pub fn mark(&self) -> Option<(usize, usize)> {
+        self.mark
+    }
+
+    // Returns (start, end) of region between mark and cursor. Both positions are ensured to be
+    // inside the buffer. Returns None when mark is not set
+    pub fn region(&self) -> Option<((usize, usize), (usize, usize))> {
+        if self.row.is_empty() {
+            return None; // Empty file has no text to select
+        }
+        let clamp = |(x, y): (usize, usize)| {
+            if y < self.row.len() {
+                (cmp::min(x, self.row[y].len()), y)
+            } else {
+                // Cursor is placed at the line after the end of buffer
+                let y = self.row.len() - 1;
+                (self.row[y].len(), y)
+            }
+        };
+        let mark = clamp(self.mark?);
+        let cursor = clamp(self.cursor());
+        // Compare (y, x) order
+        if (mark.1, mark.0) <= (cursor.1, cursor.0) {
+            Some((mark, cursor))
+        } else {
+            Some((cursor, mark))
+        }
+    }
+
+    fn text_in_range(&self, (sx, sy): (usize, usize), (ex, ey): (usize, usize)) -> String {
+        if sy == ey {
+            return self.row[sy][sx..ex].to_owned();
+        }
+        let mut text = self.row[sy][sx..].to_owned();
+        for row in self.row.iter().skip(sy + 1).take(ey - sy - 1) {
+            text.push('\n');
+            text.push_str(row.buffer());
+        }
+        text.push('\n');
+        text.push_str(&self.row[ey][..ex]);
+        text
+    }
+
+    pub fn region_text(&self) -> Option<String> {
+        let (start, end) = self.region()?;
+        Some(self.text_in_range(start, end))
+    }
+
     fn after_undoredo(&mut self, state: Option<(usize, usize, usize, bool)>) -> bool {
         match state {
             Some((x, y, s, _)) => {
+                *self.whole_text.get_mut() = None;
+                self.mark = None;
                 self.set_cursor(x, y);
                 self.set_dirty_start(s);
                 true
@@ -547,7 +1132,7 @@ impl TextBuffer {
     }
 
     pub fn undo(&mut self) -> bool {
-        let state = self.history.undo(&mut self.row);
+        let state = self.history.undo(&mut self.row, &mut self.line_ending);
         if let Some((_, _, _, edited)) = state {
             // If edited is true, it means that undo target is the ongoing change. In the case,
             // undo point is not consumed and undo count should not be decreased
@@ -560,7 +1145,7 @@ impl TextBuffer {
     }
 
     pub fn redo(&mut self) -> bool {
-        let state = self.history.redo(&mut self.row);
+        let state = self.history.redo(&mut self.row, &mut self.line_ending);
         if let Some((_, _, _, edited)) = state {
             // If edited is true, it means that redo target is the ongoing change. In the case,
             // redo does not happen since the new ongoing change is happening and undo count should